@@ -0,0 +1,5 @@
+create table persisted_queries (
+    hash varchar primary key,
+    query text not null,
+    created_at timestamp not null default now()
+);
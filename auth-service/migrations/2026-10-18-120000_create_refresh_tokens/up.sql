@@ -0,0 +1,17 @@
+create table refresh_tokens (
+    id serial primary key,
+    user_id integer not null references users (id) on delete cascade,
+    token_hash varchar(64) not null unique,
+    -- tokens which replaced each other on refresh belong to the same session
+    session_id varchar(36) not null,
+    access_token_jti varchar(36) not null,
+    access_token_expires_at timestamp not null,
+    expires_at timestamp not null,
+    created_at timestamp not null default now(),
+    used_at timestamp,
+    revoked_at timestamp
+);
+
+create index refresh_tokens_session_idx on refresh_tokens (session_id);
+create index refresh_tokens_user_idx on refresh_tokens (user_id);
+create index refresh_tokens_revoked_idx on refresh_tokens (access_token_expires_at) where revoked_at is not null;
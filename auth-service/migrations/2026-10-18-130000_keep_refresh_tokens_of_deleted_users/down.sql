@@ -0,0 +1,5 @@
+delete from refresh_tokens where user_id is null;
+alter table refresh_tokens drop constraint refresh_tokens_user_id_fkey;
+alter table refresh_tokens add constraint refresh_tokens_user_id_fkey
+    foreign key (user_id) references users (id) on delete cascade;
+alter table refresh_tokens alter column user_id set not null;
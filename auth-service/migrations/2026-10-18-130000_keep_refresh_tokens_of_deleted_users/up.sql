@@ -0,0 +1,5 @@
+-- revoked tokens of a deleted user stay in the table, so the gateway keeps rejecting their access tokens
+alter table refresh_tokens alter column user_id drop not null;
+alter table refresh_tokens drop constraint refresh_tokens_user_id_fkey;
+alter table refresh_tokens add constraint refresh_tokens_user_id_fkey
+    foreign key (user_id) references users (id) on delete set null;
@@ -0,0 +1,21 @@
+-- counters of failed sign-in attempts, if they're stored in Postgres
+create table sign_in_attempts (
+    -- `user:<username>` or `ip:<address>`
+    subject varchar primary key,
+    failed_count integer not null,
+    last_failed_at timestamp not null,
+    locked_until timestamp
+);
+
+create table lockout_events (
+    id serial primary key,
+    subject varchar not null,
+    event_type varchar(16) not null,
+    failed_count integer,
+    locked_until timestamp,
+    -- username of an admin who unlocked an account
+    actor varchar,
+    created_at timestamp not null default now()
+);
+
+create index lockout_events_subject_idx on lockout_events (subject);
@@ -0,0 +1 @@
+alter table users alter column hash type varchar(122);
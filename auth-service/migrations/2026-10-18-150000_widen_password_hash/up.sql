@@ -0,0 +1,2 @@
+-- hashes made with a longer salt or output don't fit into 122 characters
+alter table users alter column hash type varchar(255);
@@ -0,0 +1,97 @@
+use std::{env, fs};
+
+use argon2::PasswordHash;
+use chrono::Utc;
+use diesel::{PgConnection, QueryResult};
+use tracing::info;
+
+use crate::persistence::model::NewUserEntity;
+use crate::persistence::repository::{self, AdminChange};
+use crate::utils::get_password_hashing;
+use crate::AuthRole;
+
+/// Admin account which is seeded at startup, so a deployment doesn't rely on demo credentials
+pub struct AdminBootstrap {
+    username: String,
+    password_hash: String,
+    first_name: String,
+    last_name: String,
+    reset_password: bool,
+}
+
+impl AdminBootstrap {
+    /// Reads `ADMIN_USERNAME` and an Argon2 hash of the admin's password in the PHC format
+    /// from `ADMIN_PASSWORD_HASH` or from the file `ADMIN_PASSWORD_HASH_FILE`, for example,
+    /// a Docker secret. `ADMIN_FIRST_NAME` and `ADMIN_LAST_NAME` are used if the account is created.
+    ///
+    /// The password is set only when the account is created, so the admin can change it with
+    /// `changeMyPassword`. To restore the configured password, for example, if it's forgotten,
+    /// start the service once with `ADMIN_PASSWORD_RESET=true`, which also revokes the admin's
+    /// sessions.
+    ///
+    /// Returns `None` if `ADMIN_USERNAME` isn't set
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Ok(username) = env::var("ADMIN_USERNAME") else {
+            return Ok(None);
+        };
+
+        let password_hash = match (
+            env::var("ADMIN_PASSWORD_HASH"),
+            env::var("ADMIN_PASSWORD_HASH_FILE"),
+        ) {
+            (Ok(_), Ok(_)) => {
+                return Err(
+                    "Only one of ADMIN_PASSWORD_HASH and ADMIN_PASSWORD_HASH_FILE can be set"
+                        .to_string(),
+                )
+            }
+            (Ok(hash), Err(_)) => hash,
+            (Err(_), Ok(path)) => fs::read_to_string(&path)
+                .map_err(|e| format!("Can't read {}: {}", path, e))?
+                .trim()
+                .to_string(),
+            (Err(_), Err(_)) => {
+                return Err(
+                    "ADMIN_PASSWORD_HASH or ADMIN_PASSWORD_HASH_FILE is required".to_string(),
+                )
+            }
+        };
+        // a malformed hash would lock the admin out instead of failing the startup
+        let parsed_hash = PasswordHash::new(&password_hash)
+            .map_err(|e| format!("Invalid admin password hash: {}", e))?;
+        let has_weaker_params = get_password_hashing()
+            .has_weaker_params(&parsed_hash)
+            .map_err(|e| format!("Invalid admin password hash: {}", e))?;
+        if has_weaker_params {
+            return Err(
+                "Admin password hash is made with weaker Argon2 parameters than configured"
+                    .to_string(),
+            );
+        }
+
+        Ok(Some(AdminBootstrap {
+            username,
+            password_hash,
+            first_name: env::var("ADMIN_FIRST_NAME").unwrap_or_else(|_| "Admin".to_string()),
+            last_name: env::var("ADMIN_LAST_NAME").unwrap_or_default(),
+            reset_password: env::var("ADMIN_PASSWORD_RESET").is_ok_and(|value| value == "true"),
+        }))
+    }
+
+    /// Creates the account or restores the admin role and, if requested, the password;
+    /// other users aren't touched. Nothing is written if the account is already up to date,
+    /// so it's safe to run on every start; otherwise, sessions of the admin are revoked
+    pub fn run(&self, conn: &mut PgConnection) -> QueryResult<AdminChange> {
+        let admin = NewUserEntity {
+            username: self.username.clone(),
+            hash: self.password_hash.clone(),
+            first_name: self.first_name.clone(),
+            last_name: self.last_name.clone(),
+            role: AuthRole::Admin.to_string(),
+        };
+        let change =
+            repository::upsert_admin(admin, self.reset_password, Utc::now().naive_utc(), conn)?;
+        info!(username = %self.username, %change, "Bootstrapped admin account");
+        Ok(change)
+    }
+}
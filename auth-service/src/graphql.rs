@@ -1,14 +1,33 @@
 use std::str::FromStr;
 
+use argon2::password_hash::Error as PasswordHashError;
+use async_graphql::connection::{query, Connection};
 use async_graphql::*;
+use chrono::Utc;
+use diesel::result::Error as DieselError;
+use diesel::OptionalExtension;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
+use tracing::warn;
+use uuid::Uuid;
 
-use common_utils::{CustomError, FORBIDDEN_MESSAGE};
+use common_utils::error::{self, ErrorCode, ServiceError, ServiceResult};
+use common_utils::pagination::{self, IdCursor, KeysetPage};
+use common_utils::permissions::PermissionGuard;
+use common_utils::{Username, FORBIDDEN_MESSAGE};
 
-use crate::persistence::model::{NewUserEntity, UserEntity};
+use crate::lockout::{RequestAddresses, SignInLimiter};
+use crate::permissions::{ACCOUNT_WRITE, USERS_READ, USERS_WRITE};
+use crate::persistence::model::{
+    NewRefreshTokenEntity, NewUserEntity, RefreshTokenEntity, UserChangeset, UserEntity,
+};
 use crate::persistence::repository;
-use crate::utils::{create_jwt_token, get_jwt_secret_key, hash_password, verify_password};
+use crate::utils::{
+    create_jwt_token, generate_refresh_token, get_jwt_keys, hash_password, hash_refresh_token,
+    refresh_token_expiration, verify_password, PasswordMatch,
+};
 use crate::{get_conn_from_ctx, AuthRole};
 
 pub type AppSchema = Schema<Query, Mutation, EmptySubscription>;
@@ -17,12 +36,51 @@ pub struct Query;
 
 #[Object]
 impl Query {
-    async fn get_users(&self, ctx: &Context<'_>) -> Vec<User> {
-        repository::get_all(&mut get_conn_from_ctx(ctx))
-            .expect("Can't get planets")
-            .iter()
-            .map(User::from)
-            .collect()
+    #[graphql(
+        guard = "PermissionGuard::new(USERS_READ)",
+        complexity = "pagination::page_complexity(first, last, child_complexity)"
+    )]
+    async fn get_users(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<IdCursor, User>> {
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = KeysetPage::new(after, before, first, last)?;
+                let mut conn = get_conn_from_ctx(ctx).await?;
+                let user_entities = repository::get_page(&page, &mut conn).await?;
+                let users = user_entities
+                    .iter()
+                    .map(|u| Ok((u.id, User::try_from(u)?)))
+                    .collect::<ServiceResult<Vec<_>>>()?;
+                Ok::<_, ServiceError>(page.into_connection(
+                    users,
+                    |(id, _)| *id,
+                    |(_, user)| user.clone(),
+                ))
+            },
+        )
+        .await
+        .map_err(|e| error::with_default_code(e, ErrorCode::BadUserInput))
+    }
+
+    #[graphql(guard = "PermissionGuard::new(USERS_READ)")]
+    async fn get_user(&self, ctx: &Context<'_>, username: String) -> ServiceResult<Option<User>> {
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        repository::get_user(&username, &mut conn)
+            .await
+            .optional()?
+            .as_ref()
+            .map(User::try_from)
+            .transpose()
     }
 }
 
@@ -30,31 +88,336 @@ pub struct Mutation;
 
 #[Object]
 impl Mutation {
-    #[graphql(guard = "RoleGuard::new(AuthRole::Admin)")]
-    async fn create_user(&self, ctx: &Context<'_>, user: UserInput) -> Result<User> {
+    #[graphql(guard = "PermissionGuard::new(USERS_WRITE)")]
+    async fn create_user(&self, ctx: &Context<'_>, user: UserInput) -> ServiceResult<User> {
         let new_user = NewUserEntity {
             username: user.username,
-            hash: hash_password(user.password.as_str())?,
+            hash: hash_new_password(&user.password)?,
             first_name: user.first_name,
             last_name: user.last_name,
             role: user.role.to_string(),
         };
 
-        let created_user_entity = repository::create(new_user, &mut get_conn_from_ctx(ctx))?;
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let created_user_entity = repository::create(new_user, &mut conn).await?;
+
+        User::try_from(&created_user_entity)
+    }
+
+    #[graphql(guard = "PermissionGuard::new(USERS_WRITE)")]
+    async fn update_user(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        user: UserPatchInput,
+    ) -> ServiceResult<User> {
+        let changeset = UserChangeset {
+            first_name: user.first_name,
+            last_name: user.last_name,
+            ..Default::default()
+        };
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let updated_user_entity = repository::update(&username, changeset, &mut conn).await?;
+
+        User::try_from(&updated_user_entity)
+    }
+
+    /// Deletes a user and revokes their sessions
+    #[graphql(guard = "PermissionGuard::new(USERS_WRITE)")]
+    async fn delete_user(&self, ctx: &Context<'_>, username: String) -> ServiceResult<User> {
+        check_not_current_user(ctx, &username, "You can't delete yourself")?;
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let deleted_user_entity = conn
+            .transaction(move |conn| {
+                async move {
+                    let user = repository::get_user(&username, conn).await?;
+                    repository::revoke_user_sessions(user.id, Utc::now().naive_utc(), conn).await?;
+                    Ok::<_, ServiceError>(repository::delete(user.id, conn).await?)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        User::try_from(&deleted_user_entity)
+    }
+
+    /// Sessions of the user are revoked, since their tokens contain the previous role
+    #[graphql(guard = "PermissionGuard::new(USERS_WRITE)")]
+    async fn change_role(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        role: Role,
+    ) -> ServiceResult<User> {
+        // otherwise the last admin could lock everyone out
+        check_not_current_user(ctx, &username, "You can't change your own role")?;
+        let changeset = UserChangeset {
+            role: Some(role.to_string()),
+            ..Default::default()
+        };
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let updated_user_entity =
+            update_and_revoke_sessions(username, changeset, &mut conn).await?;
+
+        User::try_from(&updated_user_entity)
+    }
+
+    /// Sets a new password of a user and revokes their sessions
+    #[graphql(guard = "PermissionGuard::new(USERS_WRITE)")]
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        username: String,
+        new_password: String,
+    ) -> ServiceResult<bool> {
+        let changeset = UserChangeset {
+            hash: Some(hash_new_password(&new_password)?),
+            ..Default::default()
+        };
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        update_and_revoke_sessions(username, changeset, &mut conn).await?;
+        Ok(true)
+    }
+
+    /// Changes the password of the signed-in user; all of their sessions are revoked,
+    /// so they have to sign in again
+    #[graphql(guard = "PermissionGuard::new(ACCOUNT_WRITE)")]
+    async fn change_my_password(
+        &self,
+        ctx: &Context<'_>,
+        current_password: String,
+        new_password: String,
+    ) -> ServiceResult<bool> {
+        let username = ctx
+            .data_opt::<Username>()
+            .map(|username| username.0.clone())
+            .ok_or_else(|| ServiceError::forbidden(FORBIDDEN_MESSAGE))?;
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let user = repository::get_user(&username, &mut conn).await?;
+        verify_password(&user.hash, &current_password).map_err(|e| match e {
+            PasswordHashError::Password => ServiceError::forbidden("Invalid password"),
+            e => ServiceError::internal(format!("Can't verify password: {}", e)),
+        })?;
+
+        let changeset = UserChangeset {
+            hash: Some(hash_new_password(&new_password)?),
+            ..Default::default()
+        };
+        update_and_revoke_sessions(username, changeset, &mut conn).await?;
+        Ok(true)
+    }
+
+    /// Usernames and client IPs are locked out for a while after repeated failures
+    async fn sign_in(&self, ctx: &Context<'_>, input: SignInInput) -> ServiceResult<TokenPair> {
+        let limiter = ctx.data::<SignInLimiter>()?;
+        let client_ip = ctx
+            .data_opt::<RequestAddresses>()
+            .and_then(|addresses| limiter.client_ip(addresses));
+        let client_ip = client_ip.as_ref();
+        let now = Utc::now().naive_utc();
+        limiter.check(&input.username, client_ip, now).await?;
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let user = repository::get_user(&input.username, &mut conn)
+            .await
+            .optional()?;
+        let password_match = match &user {
+            Some(user) => match verify_password(&user.hash, &input.password) {
+                Ok(password_match) => Some(password_match),
+                Err(PasswordHashError::Password) => None,
+                Err(e) => {
+                    return Err(ServiceError::internal(format!(
+                        "Can't verify password: {}",
+                        e
+                    )))
+                }
+            },
+            None => None,
+        };
+        // an unknown username and a wrong password aren't distinguished for a client
+        let (Some(user), Some(password_match)) = (user, password_match) else {
+            limiter
+                .record_failure(&input.username, client_ip, now)
+                .await?;
+            return Err(invalid_credentials_error());
+        };
+        limiter.record_success(&user.username).await?;
+        if password_match == PasswordMatch::Outdated {
+            upgrade_password_hash(&user.username, &input.password, &mut conn).await;
+        }
+
+        // a session is started by signing in and lasts while its tokens are refreshed
+        let session_id = Uuid::new_v4().to_string();
+        issue_tokens(&user, session_id, &mut conn).await
+    }
+
+    /// Resets failed sign-in attempts of a user; returns `false` if there were none
+    #[graphql(guard = "PermissionGuard::new(USERS_WRITE)")]
+    async fn unlock_user(&self, ctx: &Context<'_>, username: String) -> ServiceResult<bool> {
+        let admin = ctx.data_opt::<Username>().map(|admin| admin.0.as_str());
+        ctx.data::<SignInLimiter>()?
+            .unlock_user(&username, admin)
+            .await
+    }
+
+    /// Exchanges a refresh token for a new pair of tokens; the refresh token can't be used again
+    async fn refresh_token(
+        &self,
+        ctx: &Context<'_>,
+        refresh_token: String,
+    ) -> ServiceResult<TokenPair> {
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let token = get_valid_refresh_token(&refresh_token, &mut conn).await?;
+
+        let now = Utc::now().naive_utc();
+        if !repository::mark_refresh_token_used(token.id, now, &mut conn).await? {
+            // the token is used twice, so it may be stolen: neither a client nor an attacker
+            // can continue the session
+            repository::revoke_session(&token.session_id, now, &mut conn).await?;
+            return Err(invalid_refresh_token_error());
+        }
+
+        let user_id = token.user_id.ok_or_else(invalid_refresh_token_error)?;
+        let user = repository::get_user_by_id(user_id, &mut conn).await?;
+        issue_tokens(&user, token.session_id, &mut conn).await
+    }
+
+    /// Revokes the session of a refresh token, including its access token
+    async fn sign_out(&self, ctx: &Context<'_>, refresh_token: String) -> ServiceResult<bool> {
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let token = get_valid_refresh_token(&refresh_token, &mut conn).await?;
+        repository::revoke_session(&token.session_id, Utc::now().naive_utc(), &mut conn).await?;
+        Ok(true)
+    }
+
+    /// Revokes all sessions of the user which a refresh token belongs to
+    async fn revoke_all_sessions(
+        &self,
+        ctx: &Context<'_>,
+        refresh_token: String,
+    ) -> ServiceResult<bool> {
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let token = get_valid_refresh_token(&refresh_token, &mut conn).await?;
+        let user_id = token.user_id.ok_or_else(invalid_refresh_token_error)?;
+        repository::revoke_user_sessions(user_id, Utc::now().naive_utc(), &mut conn).await?;
+        Ok(true)
+    }
+}
+
+fn hash_new_password(password: &str) -> ServiceResult<String> {
+    hash_password(password)
+        .map_err(|e| ServiceError::internal(format!("Can't hash password: {}", e)))
+}
+
+/// Replaces a hash made with weaker parameters; a failure doesn't prevent signing in
+async fn upgrade_password_hash(username: &str, password: &str, conn: &mut AsyncPgConnection) {
+    let changeset = match hash_new_password(password) {
+        Ok(hash) => UserChangeset {
+            hash: Some(hash),
+            ..Default::default()
+        },
+        Err(e) => {
+            warn!(error = e.message, "Can't re-hash password");
+            return;
+        }
+    };
+    if let Err(e) = repository::update(username, changeset, conn).await {
+        warn!(error = %e, "Can't update password hash");
+    }
+}
 
-        Ok(User::from(&created_user_entity))
+fn check_not_current_user(ctx: &Context<'_>, username: &str, message: &str) -> ServiceResult<()> {
+    match ctx.data_opt::<Username>() {
+        Some(current_username) if current_username.0 == username => {
+            Err(ServiceError::bad_user_input(message))
+        }
+        _ => Ok(()),
     }
+}
+
+/// Updates a user and revokes their sessions, so tokens with stale claims can't be refreshed
+async fn update_and_revoke_sessions(
+    username: String,
+    changeset: UserChangeset,
+    conn: &mut AsyncPgConnection,
+) -> ServiceResult<UserEntity> {
+    conn.transaction(move |conn| {
+        async move {
+            let user = repository::update(&username, changeset, conn).await?;
+            repository::revoke_user_sessions(user.id, Utc::now().naive_utc(), conn).await?;
+            Ok::<_, ServiceError>(user)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+fn invalid_credentials_error() -> ServiceError {
+    ServiceError::forbidden("Invalid username or password")
+}
+
+fn invalid_refresh_token_error() -> ServiceError {
+    ServiceError::forbidden("Invalid refresh token")
+}
 
-    async fn sign_in(&self, ctx: &Context<'_>, input: SignInInput) -> Result<String> {
-        let user = repository::get_user(&input.username, &mut get_conn_from_ctx(ctx))?;
-        verify_password(&user.hash, &input.password)?;
-        let role = AuthRole::from_str(user.role.as_str())?;
-        let new_token = create_jwt_token(user.username, role, &get_jwt_secret_key())?;
-        Ok(new_token)
+/// Finds a refresh token that is neither expired nor revoked
+async fn get_valid_refresh_token(
+    refresh_token: &str,
+    conn: &mut AsyncPgConnection,
+) -> ServiceResult<RefreshTokenEntity> {
+    let token = repository::get_refresh_token(&hash_refresh_token(refresh_token), conn)
+        .await
+        .map_err(|e| match e {
+            DieselError::NotFound => invalid_refresh_token_error(),
+            e => ServiceError::from(e),
+        })?;
+    if token.revoked_at.is_some() || token.expires_at <= Utc::now().naive_utc() {
+        return Err(invalid_refresh_token_error());
     }
+    Ok(token)
+}
+
+async fn issue_tokens(
+    user: &UserEntity,
+    session_id: String,
+    conn: &mut AsyncPgConnection,
+) -> ServiceResult<TokenPair> {
+    let role = AuthRole::from_str(user.role.as_str())
+        .map_err(|_| ServiceError::internal(format!("Unknown role: {}", user.role)))?;
+    let access_token = create_jwt_token(user.username.clone(), role, get_jwt_keys())
+        .map_err(|e| ServiceError::internal(format!("Can't create token: {}", e)))?;
+
+    let refresh_token = generate_refresh_token();
+    let new_token = NewRefreshTokenEntity {
+        user_id: Some(user.id),
+        token_hash: hash_refresh_token(&refresh_token),
+        session_id,
+        access_token_jti: access_token.jti,
+        access_token_expires_at: access_token.expires_at,
+        expires_at: refresh_token_expiration(Utc::now().naive_utc()),
+    };
+    repository::create_refresh_token(new_token, conn).await?;
+
+    Ok(TokenPair {
+        access_token: access_token.token,
+        refresh_token,
+    })
 }
 
 #[derive(SimpleObject)]
+struct TokenPair {
+    /// JWT to send in the `Authorization` header
+    access_token: String,
+    /// Opaque token to get a new pair when the access token expires
+    refresh_token: String,
+}
+
+#[derive(Clone, SimpleObject)]
 struct User {
     username: String,
     first_name: String,
@@ -71,6 +434,12 @@ struct UserInput {
     role: Role,
 }
 
+#[derive(InputObject)]
+struct UserPatchInput {
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum Role {
@@ -84,40 +453,18 @@ struct SignInInput {
     password: String,
 }
 
-impl From<&UserEntity> for User {
-    fn from(entity: &UserEntity) -> Self {
-        User {
+impl TryFrom<&UserEntity> for User {
+    type Error = ServiceError;
+
+    fn try_from(entity: &UserEntity) -> Result<Self, Self::Error> {
+        let role = Role::from_str(entity.role.as_str())
+            .map_err(|_| ServiceError::internal(format!("Unknown role: {}", entity.role)))?;
+
+        Ok(User {
             username: entity.username.clone(),
             first_name: entity.first_name.clone(),
             last_name: entity.last_name.clone(),
-            role: Role::from_str(entity.role.as_str()).expect("Can't convert &str to Role"),
-        }
-    }
-}
-
-struct RoleGuard {
-    role: AuthRole,
-}
-
-impl RoleGuard {
-    fn new(role: AuthRole) -> Self {
-        Self { role }
-    }
-}
-
-impl Guard for RoleGuard {
-    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        let maybe_getting_role_result = ctx.data_opt::<Result<Option<AuthRole>, CustomError>>();
-        match maybe_getting_role_result {
-            Some(getting_role_result) => {
-                let check_role_result =
-                    common_utils::check_user_role_is_allowed(getting_role_result, &self.role);
-                match check_role_result {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(Error::new(e.message)),
-                }
-            }
-            None => Err(FORBIDDEN_MESSAGE.into()),
-        }
+            role,
+        })
     }
 }
@@ -0,0 +1,177 @@
+// WARNING: THIS IS ONLY FOR DEMO! PLEASE DO MORE RESEARCH FOR PRODUCTION USE.
+use std::fs;
+use std::path::Path;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// SHA-256 of public parts of the demo keys committed to the repository; anyone can sign
+// tokens with them, so they are rejected unless `ALLOW_DEMO_JWT_KEYS` is set
+const DEMO_KEY_FINGERPRINTS: [&str; 2] = [
+    "bdde8aa4a454a2092b4f87b7a2b95afa470b90ca6747be36d0a3b6c1bae8157c",
+    "7bb439059cdc2f73d86f994c49da236de66da295bee404061a2329f7a6d7bdf4",
+];
+
+/// Private keys which tokens are signed with, and their public parts published as a JWKS
+pub struct JwtKeys {
+    signing_key_id: String,
+    signing_algorithm: Algorithm,
+    signing_key: EncodingKey,
+    jwks: JwkSet,
+}
+
+impl JwtKeys {
+    /// Reads private keys in the PKCS#8 PEM format from files `<key ID>.pem` in `JWT_KEYS_DIR`.
+    /// Tokens are signed with key `JWT_SIGNING_KEY_ID`; other keys are only published,
+    /// so tokens signed with them before a rotation stay valid until they expire.
+    /// The demo keys of the repository are accepted only if `ALLOW_DEMO_JWT_KEYS` is `true`
+    pub fn from_env() -> Result<Self, String> {
+        let dir = std::env::var("JWT_KEYS_DIR").map_err(|_| "Can't read JWT_KEYS_DIR")?;
+        let signing_key_id =
+            std::env::var("JWT_SIGNING_KEY_ID").map_err(|_| "Can't read JWT_SIGNING_KEY_ID")?;
+        let allow_demo_keys =
+            std::env::var("ALLOW_DEMO_JWT_KEYS").is_ok_and(|value| value == "true");
+        Self::from_dir(Path::new(&dir), signing_key_id, allow_demo_keys)
+    }
+
+    pub fn from_dir(
+        dir: &Path,
+        signing_key_id: String,
+        allow_demo_keys: bool,
+    ) -> Result<Self, String> {
+        let mut paths = fs::read_dir(dir)
+            .map_err(|e| format!("Can't read {}: {}", dir.display(), e))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Can't read {}: {}", dir.display(), e))?;
+        paths.retain(|path| path.extension().is_some_and(|extension| extension == "pem"));
+        paths.sort();
+
+        let mut signing_key = None;
+        let mut keys = Vec::new();
+        for path in paths {
+            let key_id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| format!("Invalid key file name: {}", path.display()))?
+                .to_string();
+            let pem =
+                fs::read(&path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+            let key = parse_key(&key_id, &pem)
+                .map_err(|e| format!("Invalid key {}: {}", path.display(), e))?;
+            if !allow_demo_keys && DEMO_KEY_FINGERPRINTS.contains(&key.fingerprint.as_str()) {
+                return Err(format!(
+                    "Key {} is a demo key of the repository, provide your own keys in JWT_KEYS_DIR",
+                    path.display()
+                ));
+            }
+            if key_id == signing_key_id {
+                signing_key = Some((key.algorithm, key.encoding_key));
+            }
+            keys.push(key.jwk);
+        }
+
+        let (signing_algorithm, signing_key) =
+            signing_key.ok_or_else(|| format!("Signing key {} isn't found", signing_key_id))?;
+        Ok(JwtKeys {
+            signing_key_id,
+            signing_algorithm,
+            signing_key,
+            jwks: JwkSet { keys },
+        })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let mut header = Header::new(self.signing_algorithm);
+        header.kid = Some(self.signing_key_id.clone());
+        encode(&header, claims, &self.signing_key)
+    }
+
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+struct ParsedKey {
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    jwk: Jwk,
+    /// SHA-256 of the public key in the hex format
+    fingerprint: String,
+}
+
+// RSA keys are used for RS256 and Ed25519 keys for EdDSA
+fn parse_key(key_id: &str, pem: &[u8]) -> Result<ParsedKey, String> {
+    let der = pem::parse(pem).map_err(|e| e.to_string())?;
+
+    let (algorithm, key_algorithm, encoding_key, parameters, public_key) =
+        if let Ok(key_pair) = RsaKeyPair::from_pkcs8(der.contents()) {
+            let public_key_components: RsaPublicKeyComponents<Vec<u8>> = key_pair.public().into();
+            let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(public_key_components.n),
+                e: URL_SAFE_NO_PAD.encode(public_key_components.e),
+            });
+            let encoding_key = EncodingKey::from_rsa_pem(pem).map_err(|e| e.to_string())?;
+            (
+                Algorithm::RS256,
+                KeyAlgorithm::RS256,
+                encoding_key,
+                parameters,
+                key_pair.public().as_ref().to_vec(),
+            )
+        } else if let Ok(key_pair) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents()) {
+            let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+            });
+            let encoding_key = EncodingKey::from_ed_pem(pem).map_err(|e| e.to_string())?;
+            (
+                Algorithm::EdDSA,
+                KeyAlgorithm::EdDSA,
+                encoding_key,
+                parameters,
+                key_pair.public_key().as_ref().to_vec(),
+            )
+        } else {
+            return Err("Only RSA and Ed25519 keys in the PKCS#8 format are supported".to_string());
+        };
+
+    Ok(ParsedKey {
+        algorithm,
+        encoding_key,
+        jwk: Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(key_id.to_string()),
+                ..Default::default()
+            },
+            algorithm: parameters,
+        },
+        fingerprint: format!("{:x}", Sha256::digest(public_key)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::JwtKeys;
+
+    #[test]
+    fn demo_keys_are_rejected_unless_allowed() {
+        let dir = Path::new("keys");
+        assert!(JwtKeys::from_dir(dir, "rsa-2026-10".to_string(), false).is_err());
+        assert!(JwtKeys::from_dir(dir, "rsa-2026-10".to_string(), true).is_ok());
+    }
+}
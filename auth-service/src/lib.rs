@@ -2,16 +2,36 @@ use actix_web::{web, HttpRequest, HttpResponse};
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql::{Context, EmptySubscription, Schema};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
-use diesel::r2d2::{ConnectionManager, PooledConnection};
+use chrono::Utc;
 use diesel::PgConnection;
+use diesel_async::pooled_connection::deadpool::Object;
+use diesel_async::AsyncPgConnection;
 use diesel_migrations::MigrationHarness;
+use tracing::{error, Instrument};
 
+use common_utils::error::ServiceResult;
+use common_utils::health;
+use common_utils::identity::Identity;
+use common_utils::metrics::{self, GraphQLMetrics};
+use common_utils::persisted_queries::{PersistedQueries, PersistedQueriesConfig};
+use common_utils::query_limits::QueryLimits;
+use common_utils::request_id::{RequestId, RequestIdInErrors};
+use common_utils::telemetry::{self, GraphQLTracing};
+use common_utils::{RevokedTokens, Username};
+
+use crate::bootstrap::AdminBootstrap;
 use crate::graphql::{AppSchema, Mutation, Query};
+use crate::lockout::{LockoutConfig, RequestAddresses, SignInLimiter};
 use crate::persistence::connection::PgPool;
 use crate::persistence::repository;
 
+pub mod bootstrap;
 pub mod graphql;
+pub mod keys;
+pub mod lockout;
+pub mod permissions;
 pub mod persistence;
+pub mod token_cleanup;
 mod utils;
 
 const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
@@ -25,17 +45,58 @@ pub fn configure_service(cfg: &mut web::ServiceConfig) {
             .route(web::post().to(index))
             .route(web::get().to(index_playground)),
     );
+    cfg.route("/metrics", web::get().to(metrics::metrics));
+    cfg.route("/health/live", web::get().to(health::live));
+    cfg.route("/health/ready", web::get().to(health::ready));
+    cfg.route("/revoked-tokens", web::get().to(revoked_tokens));
+    cfg.route("/.well-known/jwks.json", web::get().to(jwks));
 }
 
 async fn index(
     schema: web::Data<AppSchema>,
     http_req: HttpRequest,
+    request_id: Option<web::ReqData<RequestId>>,
+    identity: Option<web::ReqData<Identity>>,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
+    let span = telemetry::request_span(&http_req);
     let mut query = req.into_inner();
-    let getting_role_result = common_utils::get_role(http_req);
-    query = query.data(getting_role_result);
-    schema.execute(query).await.into()
+    if let Some(request_id) = request_id {
+        query = query.data(request_id.into_inner());
+    }
+    query = query.data(RequestAddresses::from_request(&http_req));
+    if let Some(identity) = identity {
+        let identity = identity.into_inner();
+        query = query
+            .data(Username(identity.username))
+            .data(identity.permissions);
+    }
+    schema.execute(query).instrument(span).await.into()
+}
+
+/// Public keys which the gateway verifies tokens with
+async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().json(utils::get_jwt_keys().jwks())
+}
+
+/// Polled by the gateway to reject access tokens of revoked sessions
+async fn revoked_tokens(pool: web::Data<PgPool>) -> HttpResponse {
+    let jtis = match pool.get().await {
+        Ok(mut conn) => {
+            repository::get_revoked_access_token_ids(Utc::now().naive_utc(), &mut conn).await
+        }
+        Err(e) => {
+            error!(error = %e, "Can't get DB connection");
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+    match jtis {
+        Ok(jtis) => HttpResponse::Ok().json(RevokedTokens { jtis }),
+        Err(e) => {
+            error!(error = %e, "Can't get revoked tokens");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
 }
 
 async fn index_playground() -> HttpResponse {
@@ -46,24 +107,32 @@ async fn index_playground() -> HttpResponse {
 
 pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, Mutation, EmptySubscription> {
     Schema::build(Query, Mutation, EmptySubscription)
+        .extension(GraphQLMetrics)
+        .extension(GraphQLTracing)
+        .extension(RequestIdInErrors)
+        .extension(PersistedQueries::new(
+            &PersistedQueriesConfig::from_env().expect("Can't configure persisted queries"),
+            pool.clone(),
+        ))
+        .extension(QueryLimits::from_env().expect("Can't configure query limits"))
         .enable_federation()
+        .data(SignInLimiter::new(
+            LockoutConfig::from_env().expect("Can't configure sign-in lockout"),
+            pool.clone(),
+        ))
         .data(pool)
         .finish()
 }
 
-pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
+pub fn run_migrations(conn: &mut PgConnection) {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("Failed to run database migrations");
 
-    // if environment variable is set (in case of production environment), then update users' hash
-    if let Ok(hash) = std::env::var("SECURED_USER_PASSWORD_HASH") {
-        repository::update_password_hash(hash, conn).expect("Failed to update password hash");
-    };
+    if let Some(admin) = AdminBootstrap::from_env().expect("Can't configure admin bootstrap") {
+        admin.run(conn).expect("Failed to bootstrap admin account");
+    }
 }
 
-pub fn get_conn_from_ctx(ctx: &Context<'_>) -> PooledConnection<ConnectionManager<PgConnection>> {
-    ctx.data::<PgPool>()
-        .expect("Can't get pool")
-        .get()
-        .expect("Can't get DB connection")
+pub async fn get_conn_from_ctx(ctx: &Context<'_>) -> ServiceResult<Object<AsyncPgConnection>> {
+    Ok(ctx.data::<PgPool>()?.get().await?)
 }
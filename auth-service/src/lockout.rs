@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use actix_web::HttpRequest;
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime};
+use ipnet::IpNet;
+use strum_macros::Display;
+use tracing::warn;
+
+use common_utils::error::{ErrorCode, ServiceError, ServiceResult};
+
+use crate::persistence::connection::PgPool;
+use crate::persistence::model::NewLockoutEventEntity;
+use crate::persistence::repository;
+
+const DEFAULT_MAX_FAILED_ATTEMPTS_PER_USER: i32 = 5;
+// clients behind a NAT share an address, so it gets more attempts
+const DEFAULT_MAX_FAILED_ATTEMPTS_PER_IP: i32 = 20;
+const DEFAULT_BASE_LOCKOUT_SECS: i64 = 30;
+const DEFAULT_MAX_LOCKOUT_SECS: i64 = 3600;
+const DEFAULT_ATTEMPTS_WINDOW_SECS: i64 = 24 * 3600;
+// once there are this many counters in memory, stale ones are dropped and then the least
+// recently failed ones, so that a flood of new usernames or addresses can't exhaust memory
+const MEMORY_STORE_MAX_SUBJECTS: usize = 10_000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SignInAttemptsStorage {
+    /// Counters aren't shared between instances
+    Memory,
+    /// The `sign_in_attempts` table
+    Postgres,
+}
+
+pub struct LockoutConfig {
+    pub max_failed_attempts_per_user: i32,
+    pub max_failed_attempts_per_ip: i32,
+    /// Lockout after the last allowed attempt; it doubles with every further failure
+    pub base_lockout: Duration,
+    pub max_lockout: Duration,
+    /// Counters start over if there are no failures for this long
+    pub attempts_window: Duration,
+    pub storage: SignInAttemptsStorage,
+    /// Proxies, such as the gateway, whose `X-Forwarded-For` entries are trusted
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl LockoutConfig {
+    /// Reads `SIGN_IN_MAX_FAILED_ATTEMPTS_PER_USER`, `SIGN_IN_MAX_FAILED_ATTEMPTS_PER_IP`,
+    /// `SIGN_IN_LOCKOUT_BASE_SECS`, `SIGN_IN_LOCKOUT_MAX_SECS`, `SIGN_IN_ATTEMPTS_WINDOW_SECS`
+    /// and `SIGN_IN_ATTEMPTS_STORAGE` (`memory` by default or `postgres`).
+    /// `TRUSTED_PROXIES` is a comma-separated list of addresses or networks of proxies
+    pub fn from_env() -> Result<Self, String> {
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|e| format!("Invalid trusted proxy {}: {}", proxy, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let storage = match env::var("SIGN_IN_ATTEMPTS_STORAGE")
+            .unwrap_or_else(|_| "memory".to_string())
+            .as_str()
+        {
+            "memory" => SignInAttemptsStorage::Memory,
+            "postgres" => SignInAttemptsStorage::Postgres,
+            other => return Err(format!("Unknown sign-in attempts storage: {}", other)),
+        };
+
+        Ok(LockoutConfig {
+            max_failed_attempts_per_user: parse_setting(
+                "SIGN_IN_MAX_FAILED_ATTEMPTS_PER_USER",
+                DEFAULT_MAX_FAILED_ATTEMPTS_PER_USER,
+            )?,
+            max_failed_attempts_per_ip: parse_setting(
+                "SIGN_IN_MAX_FAILED_ATTEMPTS_PER_IP",
+                DEFAULT_MAX_FAILED_ATTEMPTS_PER_IP,
+            )?,
+            base_lockout: Duration::seconds(parse_setting(
+                "SIGN_IN_LOCKOUT_BASE_SECS",
+                DEFAULT_BASE_LOCKOUT_SECS,
+            )?),
+            max_lockout: Duration::seconds(parse_setting(
+                "SIGN_IN_LOCKOUT_MAX_SECS",
+                DEFAULT_MAX_LOCKOUT_SECS,
+            )?),
+            attempts_window: Duration::seconds(parse_setting(
+                "SIGN_IN_ATTEMPTS_WINDOW_SECS",
+                DEFAULT_ATTEMPTS_WINDOW_SECS,
+            )?),
+            storage,
+            trusted_proxies,
+        })
+    }
+
+    /// Addresses are checked from the peer back to the client; the first one which doesn't
+    /// belong to a trusted proxy is the client's, as entries before it may be spoofed
+    pub fn client_ip(&self, addresses: &RequestAddresses) -> Option<ClientIp> {
+        let is_trusted = |address: &IpAddr| {
+            self.trusted_proxies
+                .iter()
+                .any(|proxy| proxy.contains(address))
+        };
+        let peer = addresses.peer?;
+        if !is_trusted(&peer) {
+            return Some(ClientIp(peer));
+        }
+        // a trusted proxy which didn't pass an address doesn't make all its clients one client
+        for entry in addresses.forwarded_for.iter().rev() {
+            let address = parse_address(entry)?;
+            if !is_trusted(&address) {
+                return Some(ClientIp(address));
+            }
+        }
+        None
+    }
+
+    fn lockout_duration(&self, failed_count: i32, max_failed_attempts: i32) -> Option<Duration> {
+        let excess_attempts = u32::try_from(failed_count - max_failed_attempts).ok()?;
+        let lockout = 2_i32
+            .checked_pow(excess_attempts)
+            .and_then(|multiplier| self.base_lockout.checked_mul(multiplier))
+            .unwrap_or(self.max_lockout);
+        Some(lockout.min(self.max_lockout))
+    }
+}
+
+fn parse_setting<T>(name: &str, default: T) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| format!("Invalid value of {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Address of a client which signs in
+pub struct ClientIp(pub IpAddr);
+
+/// Address of the peer and entries of `X-Forwarded-For` of a request;
+/// `LockoutConfig::client_ip` decides which of them can be trusted
+pub struct RequestAddresses {
+    peer: Option<IpAddr>,
+    forwarded_for: Vec<String>,
+}
+
+impl RequestAddresses {
+    pub fn from_request(http_request: &HttpRequest) -> Self {
+        let forwarded_for = http_request
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|entry| entry.trim().to_string())
+            .filter(|entry| !entry.is_empty())
+            .collect();
+        RequestAddresses {
+            peer: http_request.peer_addr().map(|address| address.ip()),
+            forwarded_for,
+        }
+    }
+}
+
+fn parse_address(address: &str) -> Option<IpAddr> {
+    address
+        .parse::<IpAddr>()
+        .or_else(|_| address.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+}
+
+/// Failed sign-in attempts of a subject: a username or a client IP
+#[derive(Clone, Debug)]
+pub struct FailedAttempts {
+    pub failed_count: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+/// Storage of counters of failed attempts
+#[async_trait]
+pub trait AttemptStore: Send + Sync {
+    async fn get(&self, subject: &str) -> ServiceResult<Option<FailedAttempts>>;
+
+    /// Increments the counter; it starts over if the last failure happened before `reset_before`
+    async fn record_failure(
+        &self,
+        subject: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> ServiceResult<FailedAttempts>;
+
+    async fn lock(&self, subject: &str, locked_until: NaiveDateTime) -> ServiceResult<()>;
+
+    /// Returns `false` if the subject had no failed attempts
+    async fn reset(&self, subject: &str) -> ServiceResult<bool>;
+}
+
+#[derive(Default)]
+pub struct MemoryAttemptStore {
+    attempts: Mutex<HashMap<String, FailedAttempts>>,
+}
+
+impl MemoryAttemptStore {
+    fn attempts(&self) -> MutexGuard<'_, HashMap<String, FailedAttempts>> {
+        self.attempts
+            .lock()
+            .expect("Sign-in attempts lock is poisoned")
+    }
+}
+
+#[async_trait]
+impl AttemptStore for MemoryAttemptStore {
+    async fn get(&self, subject: &str) -> ServiceResult<Option<FailedAttempts>> {
+        Ok(self.attempts().get(subject).cloned())
+    }
+
+    async fn record_failure(
+        &self,
+        subject: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> ServiceResult<FailedAttempts> {
+        let mut attempts = self.attempts();
+        if attempts.len() >= MEMORY_STORE_MAX_SUBJECTS && !attempts.contains_key(subject) {
+            evict(&mut attempts, now, reset_before);
+        }
+
+        let subject_attempts = attempts
+            .entry(subject.to_string())
+            .and_modify(|attempts| {
+                if attempts.last_failed_at < reset_before {
+                    attempts.failed_count = 0;
+                    attempts.locked_until = None;
+                }
+                attempts.failed_count += 1;
+                attempts.last_failed_at = now;
+            })
+            .or_insert(FailedAttempts {
+                failed_count: 1,
+                last_failed_at: now,
+                locked_until: None,
+            });
+        Ok(subject_attempts.clone())
+    }
+
+    async fn lock(&self, subject: &str, locked_until: NaiveDateTime) -> ServiceResult<()> {
+        if let Some(attempts) = self.attempts().get_mut(subject) {
+            attempts.locked_until = Some(locked_until);
+        }
+        Ok(())
+    }
+
+    async fn reset(&self, subject: &str) -> ServiceResult<bool> {
+        Ok(self.attempts().remove(subject).is_some())
+    }
+}
+
+/// Drops stale counters and, if that isn't enough, the least recently failed ones down to 90%
+/// of the cap, so eviction doesn't happen on every failure. Locked subjects are evicted last
+fn evict(
+    attempts: &mut HashMap<String, FailedAttempts>,
+    now: NaiveDateTime,
+    reset_before: NaiveDateTime,
+) {
+    attempts.retain(|_, attempts| attempts.last_failed_at >= reset_before);
+
+    let excess = attempts
+        .len()
+        .saturating_sub(MEMORY_STORE_MAX_SUBJECTS * 9 / 10);
+    if excess == 0 {
+        return;
+    }
+    let mut subjects = attempts
+        .iter()
+        .map(|(subject, attempts)| {
+            let locked = attempts
+                .locked_until
+                .is_some_and(|locked_until| locked_until > now);
+            ((locked, attempts.last_failed_at), subject.clone())
+        })
+        .collect::<Vec<_>>();
+    subjects.sort_unstable();
+    for (_, subject) in subjects.into_iter().take(excess) {
+        attempts.remove(&subject);
+    }
+}
+
+pub struct PostgresAttemptStore {
+    pool: PgPool,
+}
+
+impl PostgresAttemptStore {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresAttemptStore { pool }
+    }
+}
+
+#[async_trait]
+impl AttemptStore for PostgresAttemptStore {
+    async fn get(&self, subject: &str) -> ServiceResult<Option<FailedAttempts>> {
+        let mut conn = self.pool.get().await?;
+        let entity = repository::get_sign_in_attempts(subject, &mut conn).await?;
+        Ok(entity.map(|entity| FailedAttempts {
+            failed_count: entity.failed_count,
+            last_failed_at: entity.last_failed_at,
+            locked_until: entity.locked_until,
+        }))
+    }
+
+    async fn record_failure(
+        &self,
+        subject: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> ServiceResult<FailedAttempts> {
+        let mut conn = self.pool.get().await?;
+        let entity =
+            repository::record_failed_sign_in(subject, now, reset_before, &mut conn).await?;
+        Ok(FailedAttempts {
+            failed_count: entity.failed_count,
+            last_failed_at: entity.last_failed_at,
+            locked_until: entity.locked_until,
+        })
+    }
+
+    async fn lock(&self, subject: &str, locked_until: NaiveDateTime) -> ServiceResult<()> {
+        let mut conn = self.pool.get().await?;
+        repository::lock_sign_in(subject, locked_until, &mut conn).await?;
+        Ok(())
+    }
+
+    async fn reset(&self, subject: &str) -> ServiceResult<bool> {
+        let mut conn = self.pool.get().await?;
+        Ok(repository::reset_sign_in_attempts(subject, &mut conn).await?)
+    }
+}
+
+#[derive(Display)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+enum LockoutEventType {
+    Locked,
+    Unlocked,
+}
+
+/// Temporarily locks out usernames and client IPs after repeated failed sign-ins;
+/// lockouts and unlocks are recorded in the `lockout_events` table
+pub struct SignInLimiter {
+    config: LockoutConfig,
+    store: Arc<dyn AttemptStore>,
+    pool: PgPool,
+    unknown_client_ip_reported: AtomicBool,
+}
+
+impl SignInLimiter {
+    pub fn new(config: LockoutConfig, pool: PgPool) -> Self {
+        let store: Arc<dyn AttemptStore> = match config.storage {
+            SignInAttemptsStorage::Memory => Arc::new(MemoryAttemptStore::default()),
+            SignInAttemptsStorage::Postgres => Arc::new(PostgresAttemptStore::new(pool.clone())),
+        };
+        Self::with_store(config, store, pool)
+    }
+
+    pub fn with_store(config: LockoutConfig, store: Arc<dyn AttemptStore>, pool: PgPool) -> Self {
+        SignInLimiter {
+            config,
+            store,
+            pool,
+            unknown_client_ip_reported: AtomicBool::new(false),
+        }
+    }
+
+    /// Fails if either the username or the client IP is locked
+    pub async fn check(
+        &self,
+        username: &str,
+        client_ip: Option<&ClientIp>,
+        now: NaiveDateTime,
+    ) -> ServiceResult<()> {
+        for subject in subjects(username, client_ip) {
+            let locked_until = self
+                .store
+                .get(&subject)
+                .await?
+                .and_then(|attempts| attempts.locked_until)
+                .filter(|locked_until| *locked_until > now);
+            if let Some(locked_until) = locked_until {
+                return Err(ServiceError::new(
+                    ErrorCode::TooManyRequests,
+                    format!(
+                        "Too many failed sign-in attempts, try again in {} seconds",
+                        (locked_until - now).num_seconds().max(1)
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        client_ip: Option<&ClientIp>,
+        now: NaiveDateTime,
+    ) -> ServiceResult<()> {
+        let reset_before = now - self.config.attempts_window;
+        let user_subject = user_subject(username);
+        for subject in subjects(username, client_ip) {
+            let max_failed_attempts = if subject == user_subject {
+                self.config.max_failed_attempts_per_user
+            } else {
+                self.config.max_failed_attempts_per_ip
+            };
+
+            let attempts = self
+                .store
+                .record_failure(&subject, now, reset_before)
+                .await?;
+            let Some(lockout) = self
+                .config
+                .lockout_duration(attempts.failed_count, max_failed_attempts)
+            else {
+                continue;
+            };
+
+            let locked_until = now + lockout;
+            self.store.lock(&subject, locked_until).await?;
+            warn!(%subject, failed_count = attempts.failed_count, %locked_until, "Sign-in is locked");
+            self.record_event(NewLockoutEventEntity {
+                subject,
+                event_type: LockoutEventType::Locked.to_string(),
+                failed_count: Some(attempts.failed_count),
+                locked_until: Some(locked_until),
+                actor: None,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Only the username's counter is reset: otherwise signing in to an own account
+    /// would let an attacker keep guessing passwords of others from the same IP
+    pub async fn record_success(&self, username: &str) -> ServiceResult<()> {
+        self.store.reset(&user_subject(username)).await?;
+        Ok(())
+    }
+
+    /// Returns `false` if the user had no failed attempts
+    pub async fn unlock_user(&self, username: &str, admin: Option<&str>) -> ServiceResult<bool> {
+        let subject = user_subject(username);
+        if !self.store.reset(&subject).await? {
+            return Ok(false);
+        }
+        self.record_event(NewLockoutEventEntity {
+            subject,
+            event_type: LockoutEventType::Unlocked.to_string(),
+            failed_count: None,
+            locked_until: None,
+            actor: admin.map(str::to_string),
+        })
+        .await?;
+        Ok(true)
+    }
+
+    pub fn client_ip(&self, addresses: &RequestAddresses) -> Option<ClientIp> {
+        let client_ip = self.config.client_ip(addresses);
+        // reported once, as it's a misconfiguration of the proxies rather than of a request
+        if client_ip.is_none()
+            && addresses.peer.is_some()
+            && !self
+                .unknown_client_ip_reported
+                .swap(true, Ordering::Relaxed)
+        {
+            warn!(
+                "A trusted proxy didn't pass a client's address, so sign-in attempts \
+                aren't limited per IP; check TRUSTED_PROXIES and the proxies' configuration"
+            );
+        }
+        client_ip
+    }
+
+    async fn record_event(&self, event: NewLockoutEventEntity) -> ServiceResult<()> {
+        let mut conn = self.pool.get().await?;
+        repository::create_lockout_event(event, &mut conn).await?;
+        Ok(())
+    }
+}
+
+fn user_subject(username: &str) -> String {
+    format!("user:{}", username)
+}
+
+fn subjects(username: &str, client_ip: Option<&ClientIp>) -> Vec<String> {
+    let mut subjects = vec![user_subject(username)];
+    if let Some(client_ip) = client_ip {
+        subjects.push(format!("ip:{}", client_ip.0));
+    }
+    subjects
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{AttemptStore, MemoryAttemptStore, MEMORY_STORE_MAX_SUBJECTS};
+
+    #[actix_rt::test]
+    async fn memory_store_is_capped() {
+        let store = MemoryAttemptStore::default();
+        let start = Utc::now().naive_utc();
+        let reset_before = start - Duration::days(1);
+
+        store
+            .record_failure("locked", start, reset_before)
+            .await
+            .expect("Can't record failure");
+        store
+            .lock("locked", start + Duration::hours(1))
+            .await
+            .expect("Can't lock");
+        for i in 1..=MEMORY_STORE_MAX_SUBJECTS {
+            let now = start + Duration::milliseconds(i as i64);
+            store
+                .record_failure(&format!("subject_{}", i), now, reset_before)
+                .await
+                .expect("Can't record failure");
+        }
+
+        let attempts = store.attempts();
+        assert!(attempts.len() <= MEMORY_STORE_MAX_SUBJECTS);
+        assert!(attempts.contains_key("locked"));
+        assert!(!attempts.contains_key("subject_1"));
+        assert!(attempts.contains_key(&format!("subject_{}", MEMORY_STORE_MAX_SUBJECTS)));
+    }
+}
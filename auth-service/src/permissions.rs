@@ -0,0 +1,32 @@
+use std::collections::BTreeSet;
+
+use crate::AuthRole;
+
+pub const ACCOUNT_WRITE: &str = "account:write";
+pub const PLANETS_WRITE: &str = "planets:write";
+pub const USERS_READ: &str = "users:read";
+pub const USERS_WRITE: &str = "users:write";
+
+/// Roles which a role includes, so it's granted their permissions too
+fn included_roles(role: &AuthRole) -> &'static [AuthRole] {
+    match role {
+        AuthRole::Admin => &[AuthRole::User],
+        AuthRole::User => &[],
+    }
+}
+
+fn own_permissions(role: &AuthRole) -> &'static [&'static str] {
+    match role {
+        AuthRole::Admin => &[PLANETS_WRITE, USERS_READ, USERS_WRITE],
+        AuthRole::User => &[ACCOUNT_WRITE],
+    }
+}
+
+/// Permissions which are put into an access token of a user with the role
+pub fn role_permissions(role: &AuthRole) -> BTreeSet<&'static str> {
+    let mut permissions: BTreeSet<_> = own_permissions(role).iter().copied().collect();
+    for included_role in included_roles(role) {
+        permissions.extend(role_permissions(included_role));
+    }
+    permissions
+}
@@ -1,6 +1,7 @@
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
-use crate::persistence::schema::users;
+use crate::persistence::schema::{lockout_events, refresh_tokens, sign_in_attempts, users};
 
 #[derive(Identifiable, Queryable)]
 #[diesel(table_name = users)]
@@ -22,3 +23,67 @@ pub struct NewUserEntity {
     pub last_name: String,
     pub role: String,
 }
+
+#[derive(AsChangeset, Default)]
+#[diesel(table_name = users)]
+pub struct UserChangeset {
+    pub hash: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub role: Option<String>,
+}
+
+impl UserChangeset {
+    pub fn is_empty(&self) -> bool {
+        self.hash.is_none()
+            && self.first_name.is_none()
+            && self.last_name.is_none()
+            && self.role.is_none()
+    }
+}
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct RefreshTokenEntity {
+    pub id: i32,
+    // is null if the user is deleted
+    pub user_id: Option<i32>,
+    pub token_hash: String,
+    pub session_id: String,
+    pub access_token_jti: String,
+    pub access_token_expires_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshTokenEntity {
+    pub user_id: Option<i32>,
+    pub token_hash: String,
+    pub session_id: String,
+    pub access_token_jti: String,
+    pub access_token_expires_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = sign_in_attempts, primary_key(subject))]
+pub struct SignInAttemptsEntity {
+    pub subject: String,
+    pub failed_count: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = lockout_events)]
+pub struct NewLockoutEventEntity {
+    pub subject: String,
+    pub event_type: String,
+    pub failed_count: Option<i32>,
+    pub locked_until: Option<NaiveDateTime>,
+    pub actor: Option<String>,
+}
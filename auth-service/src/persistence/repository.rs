@@ -1,28 +1,338 @@
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use strum_macros::Display;
+use tracing::instrument;
 
-use crate::persistence::model::{NewUserEntity, UserEntity};
-use crate::persistence::schema::users;
+use common_utils::pagination::KeysetPage;
 
-pub fn get_all(conn: &mut PgConnection) -> QueryResult<Vec<UserEntity>> {
-    use crate::persistence::schema::users::dsl::*;
+use crate::persistence::model::{
+    NewLockoutEventEntity, NewRefreshTokenEntity, NewUserEntity, RefreshTokenEntity,
+    SignInAttemptsEntity, UserChangeset, UserEntity,
+};
+use crate::persistence::schema::{lockout_events, refresh_tokens, sign_in_attempts, users};
+
+#[instrument(skip_all)]
+pub async fn get_page(
+    page: &KeysetPage,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Vec<UserEntity>> {
+    let mut query = users::table.into_boxed();
+
+    if let Some(after) = page.after {
+        query = query.filter(users::id.gt(after));
+    }
+    if let Some(before) = page.before {
+        query = query.filter(users::id.lt(before));
+    }
 
-    users.load(conn)
+    query = if page.backward {
+        query.order(users::id.desc())
+    } else {
+        query.order(users::id.asc())
+    };
+
+    query.limit(page.fetch_limit()).load(conn).await
 }
 
-pub fn get_user(username: &str, conn: &mut PgConnection) -> QueryResult<UserEntity> {
+#[instrument(skip_all)]
+pub async fn get_user(username: &str, conn: &mut AsyncPgConnection) -> QueryResult<UserEntity> {
     users::table
         .filter(users::username.eq(username))
         .first(conn)
+        .await
+}
+
+#[instrument(skip_all)]
+pub async fn get_user_by_id(id: i32, conn: &mut AsyncPgConnection) -> QueryResult<UserEntity> {
+    users::table.find(id).first(conn).await
 }
 
-pub fn create(new_user: NewUserEntity, conn: &mut PgConnection) -> QueryResult<UserEntity> {
+#[instrument(skip_all)]
+pub async fn create(
+    new_user: NewUserEntity,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<UserEntity> {
     use crate::persistence::schema::users::dsl::*;
 
-    diesel::insert_into(users).values(new_user).get_result(conn)
+    diesel::insert_into(users)
+        .values(new_user)
+        .get_result(conn)
+        .await
 }
 
-pub fn update_password_hash(new_hash: String, conn: &mut PgConnection) -> QueryResult<usize> {
-    use crate::persistence::schema::users::dsl::*;
+/// Unchanged user is returned if the changeset is empty
+#[instrument(skip_all)]
+pub async fn update(
+    username: &str,
+    changeset: UserChangeset,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<UserEntity> {
+    if changeset.is_empty() {
+        return get_user(username, conn).await;
+    }
+
+    diesel::update(users::table.filter(users::username.eq(username)))
+        .set(changeset)
+        .get_result(conn)
+        .await
+}
+
+/// Refresh tokens of the user are kept, so access tokens of revoked sessions stay rejected
+#[instrument(skip_all)]
+pub async fn delete(id: i32, conn: &mut AsyncPgConnection) -> QueryResult<UserEntity> {
+    diesel::delete(users::table.find(id)).get_result(conn).await
+}
+
+/// What bootstrapping of the admin account changed
+#[derive(Debug, Eq, PartialEq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum AdminChange {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+/// Is run together with migrations, so it uses a synchronous connection.
+/// The password of an existing account is replaced only if `reset_password` is set.
+/// If the password or the role is replaced, sessions of the admin are revoked,
+/// as `reset_password` and `change_role` do
+#[instrument(skip_all)]
+pub fn upsert_admin(
+    admin: NewUserEntity,
+    reset_password: bool,
+    now: NaiveDateTime,
+    conn: &mut PgConnection,
+) -> QueryResult<AdminChange> {
+    conn.transaction(|conn| {
+        let existing_user = diesel::RunQueryDsl::first::<UserEntity>(
+            users::table
+                .filter(users::username.eq(&admin.username))
+                .for_update(),
+            conn,
+        )
+        .optional()?;
+
+        match existing_user {
+            None => {
+                diesel::RunQueryDsl::execute(
+                    diesel::insert_into(users::table).values(&admin),
+                    conn,
+                )?;
+                Ok(AdminChange::Created)
+            }
+            Some(user) => {
+                let changeset = UserChangeset {
+                    hash: Some(admin.hash).filter(|hash| reset_password && *hash != user.hash),
+                    role: Some(admin.role).filter(|role| *role != user.role),
+                    ..Default::default()
+                };
+                if changeset.is_empty() {
+                    return Ok(AdminChange::Unchanged);
+                }
+                diesel::RunQueryDsl::execute(
+                    diesel::update(users::table.find(user.id)).set(changeset),
+                    conn,
+                )?;
+                diesel::RunQueryDsl::execute(
+                    diesel::update(
+                        refresh_tokens::table
+                            .filter(refresh_tokens::user_id.eq(user.id))
+                            .filter(refresh_tokens::revoked_at.is_null()),
+                    )
+                    .set(refresh_tokens::revoked_at.eq(now)),
+                    conn,
+                )?;
+                Ok(AdminChange::Updated)
+            }
+        }
+    })
+}
+
+#[instrument(skip_all)]
+pub async fn create_refresh_token(
+    new_token: NewRefreshTokenEntity,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<RefreshTokenEntity> {
+    diesel::insert_into(refresh_tokens::table)
+        .values(new_token)
+        .get_result(conn)
+        .await
+}
+
+#[instrument(skip_all)]
+pub async fn get_refresh_token(
+    token_hash: &str,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<RefreshTokenEntity> {
+    refresh_tokens::table
+        .filter(refresh_tokens::token_hash.eq(token_hash))
+        .first(conn)
+        .await
+}
+
+/// Returns `false` if the token was already used, for example, by a concurrent refresh
+#[instrument(skip_all)]
+pub async fn mark_refresh_token_used(
+    id: i32,
+    now: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<bool> {
+    let updated = diesel::update(
+        refresh_tokens::table
+            .find(id)
+            .filter(refresh_tokens::used_at.is_null()),
+    )
+    .set(refresh_tokens::used_at.eq(now))
+    .execute(conn)
+    .await?;
+    Ok(updated == 1)
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_session(
+    session_id: &str,
+    now: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::update(
+        refresh_tokens::table
+            .filter(refresh_tokens::session_id.eq(session_id))
+            .filter(refresh_tokens::revoked_at.is_null()),
+    )
+    .set(refresh_tokens::revoked_at.eq(now))
+    .execute(conn)
+    .await
+}
+
+#[instrument(skip_all)]
+pub async fn revoke_user_sessions(
+    user_id: i32,
+    now: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::update(
+        refresh_tokens::table
+            .filter(refresh_tokens::user_id.eq(user_id))
+            .filter(refresh_tokens::revoked_at.is_null()),
+    )
+    .set(refresh_tokens::revoked_at.eq(now))
+    .execute(conn)
+    .await
+}
+
+/// Deletes tokens which expired, and revoked ones whose access tokens expired, before `before`.
+/// Neither can be used anymore, and a reuse of a used token matters only until it expires
+#[instrument(skip_all)]
+pub async fn delete_stale_refresh_tokens(
+    before: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::delete(
+        refresh_tokens::table.filter(
+            refresh_tokens::expires_at
+                .lt(before)
+                .or(refresh_tokens::revoked_at
+                    .is_not_null()
+                    .and(refresh_tokens::access_token_expires_at.lt(before))),
+        ),
+    )
+    .execute(conn)
+    .await
+}
+
+/// IDs of access tokens of revoked sessions; expired tokens are rejected anyway, so they're skipped
+#[instrument(skip_all)]
+pub async fn get_revoked_access_token_ids(
+    now: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Vec<String>> {
+    refresh_tokens::table
+        .filter(refresh_tokens::revoked_at.is_not_null())
+        .filter(refresh_tokens::access_token_expires_at.gt(now))
+        .select(refresh_tokens::access_token_jti)
+        .load(conn)
+        .await
+}
+
+#[instrument(skip_all)]
+pub async fn get_sign_in_attempts(
+    subject: &str,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Option<SignInAttemptsEntity>> {
+    sign_in_attempts::table
+        .find(subject)
+        .first(conn)
+        .await
+        .optional()
+}
+
+/// Increments the counter of a subject; it starts over if the last failure happened
+/// before `reset_before`
+#[instrument(skip_all)]
+pub async fn record_failed_sign_in(
+    subject: &str,
+    now: NaiveDateTime,
+    reset_before: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<SignInAttemptsEntity> {
+    diesel::delete(
+        sign_in_attempts::table
+            .find(subject)
+            .filter(sign_in_attempts::last_failed_at.lt(reset_before)),
+    )
+    .execute(conn)
+    .await?;
+
+    // the increment is atomic, so concurrent failures aren't lost
+    diesel::insert_into(sign_in_attempts::table)
+        .values(SignInAttemptsEntity {
+            subject: subject.to_string(),
+            failed_count: 1,
+            last_failed_at: now,
+            locked_until: None,
+        })
+        .on_conflict(sign_in_attempts::subject)
+        .do_update()
+        .set((
+            sign_in_attempts::failed_count.eq(sign_in_attempts::failed_count + 1),
+            sign_in_attempts::last_failed_at.eq(now),
+        ))
+        .get_result(conn)
+        .await
+}
+
+#[instrument(skip_all)]
+pub async fn lock_sign_in(
+    subject: &str,
+    locked_until: NaiveDateTime,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::update(sign_in_attempts::table.find(subject))
+        .set(sign_in_attempts::locked_until.eq(locked_until))
+        .execute(conn)
+        .await
+}
+
+/// Returns `false` if the subject had no failed attempts
+#[instrument(skip_all)]
+pub async fn reset_sign_in_attempts(
+    subject: &str,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<bool> {
+    let deleted = diesel::delete(sign_in_attempts::table.find(subject))
+        .execute(conn)
+        .await?;
+    Ok(deleted == 1)
+}
 
-    diesel::update(users).set(hash.eq(new_hash)).execute(conn)
+#[instrument(skip_all)]
+pub async fn create_lockout_event(
+    new_event: NewLockoutEventEntity,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::insert_into(lockout_events::table)
+        .values(new_event)
+        .execute(conn)
+        .await
 }
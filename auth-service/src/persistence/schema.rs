@@ -1,3 +1,39 @@
+diesel::table! {
+    lockout_events (id) {
+        id -> Int4,
+        subject -> Varchar,
+        event_type -> Varchar,
+        failed_count -> Nullable<Int4>,
+        locked_until -> Nullable<Timestamp>,
+        actor -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
+        token_hash -> Varchar,
+        session_id -> Varchar,
+        access_token_jti -> Varchar,
+        access_token_expires_at -> Timestamp,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    sign_in_attempts (subject) {
+        subject -> Varchar,
+        failed_count -> Int4,
+        last_failed_at -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -8,3 +44,12 @@ diesel::table! {
         role -> Varchar,
     }
 }
+
+diesel::joinable!(refresh_tokens -> users (user_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    lockout_events,
+    refresh_tokens,
+    sign_in_attempts,
+    users,
+);
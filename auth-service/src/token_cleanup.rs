@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::{debug, error};
+
+use common_utils::error::ServiceResult;
+
+use crate::persistence::connection::PgPool;
+use crate::persistence::repository;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+// tokens which can't be used anymore are kept for a while to help investigate sessions
+const RETENTION_HOURS: i64 = 24;
+
+/// Spawns a task that periodically deletes expired and revoked refresh tokens
+pub fn start_refresh_token_cleanup(pool: PgPool) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match delete_stale_tokens(&pool).await {
+                Ok(deleted) => debug!(deleted, "Stale refresh tokens were deleted"),
+                Err(e) => error!(error = e.message, "Stale refresh tokens weren't deleted"),
+            }
+        }
+    });
+}
+
+async fn delete_stale_tokens(pool: &PgPool) -> ServiceResult<usize> {
+    let mut conn = pool.get().await?;
+    let before = Utc::now().naive_utc() - chrono::Duration::hours(RETENTION_HOURS);
+    Ok(repository::delete_stale_refresh_tokens(before, &mut conn).await?)
+}
@@ -1,60 +1,309 @@
 // WARNING: THIS IS ONLY FOR DEMO! PLEASE DO MORE RESEARCH FOR PRODUCTION USE.
-use std::str;
+use std::env;
+use std::fmt::Display;
+use std::str::{self, FromStr};
 
 use actix_web::Result;
 use argon2::{
     password_hash::{
-        rand_core::OsRng, Error, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+        rand_core::{OsRng, RngCore},
+        Error, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
     },
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
-use chrono::{Duration, Local};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use chrono::{Duration, NaiveDateTime, Utc};
 use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
 use common_utils::Claims;
 
+use crate::keys::JwtKeys;
+use crate::permissions::role_permissions;
 use crate::AuthRole;
 
+// access tokens are short-lived, since the gateway learns about revoked ones with a delay
+const ACCESS_TOKEN_LIFETIME_MINUTES: i64 = 15;
+const REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+
 lazy_static! {
-    static ref JWT_SECRET_KEY: String =
-        std::env::var("JWT_SECRET_KEY").expect("Can't read JWT_SECRET_KEY");
+    static ref JWT_KEYS: JwtKeys = JwtKeys::from_env().expect("Can't load JWT keys");
+    static ref PASSWORD_HASHING: PasswordHashing =
+        PasswordHashing::from_env().expect("Can't configure password hashing");
+}
+
+/// Argon2id parameters of new hashes and an optional pepper: a secret which isn't stored
+/// in the database, so a leaked `users` table isn't enough to guess passwords
+pub struct PasswordHashing {
+    params: Params,
+    pepper: Option<Vec<u8>>,
+}
+
+/// A password matches a hash which is made either with the current parameters
+/// or with weaker ones, so it should be replaced
+#[derive(Debug, Eq, PartialEq)]
+pub enum PasswordMatch {
+    Current,
+    Outdated,
+}
+
+impl PasswordHashing {
+    /// Reads `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS` and `ARGON2_PARALLELISM`,
+    /// which default to the parameters recommended by OWASP, and `PASSWORD_PEPPER`
+    pub fn from_env() -> Result<Self, String> {
+        let params = Params::new(
+            parse_setting("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST)?,
+            parse_setting("ARGON2_ITERATIONS", Params::DEFAULT_T_COST)?,
+            parse_setting("ARGON2_PARALLELISM", Params::DEFAULT_P_COST)?,
+            None,
+        )
+        .map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+        let hashing = PasswordHashing {
+            params,
+            pepper: env::var("PASSWORD_PEPPER").ok().map(String::into_bytes),
+        };
+        hashing
+            .argon2(true)
+            .map_err(|e| format!("Invalid value of PASSWORD_PEPPER: {}", e))?;
+        Ok(hashing)
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash_string = self
+            .argon2(true)?
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string();
+        Ok(password_hash_string)
+    }
+
+    pub fn verify(
+        &self,
+        password_hash_string: &str,
+        input_password: &str,
+    ) -> Result<PasswordMatch, Error> {
+        let parsed_hash = PasswordHash::new(password_hash_string)?;
+        let is_peppered = match self
+            .argon2(true)?
+            .verify_password(input_password.as_bytes(), &parsed_hash)
+        {
+            Ok(()) => self.pepper.is_some(),
+            // the hash may be made before the pepper was configured
+            Err(Error::Password) if self.pepper.is_some() => {
+                self.argon2(false)?
+                    .verify_password(input_password.as_bytes(), &parsed_hash)?;
+                false
+            }
+            Err(e) => return Err(e),
+        };
+
+        if is_peppered == self.pepper.is_some() && !self.has_weaker_params(&parsed_hash)? {
+            Ok(PasswordMatch::Current)
+        } else {
+            Ok(PasswordMatch::Outdated)
+        }
+    }
+
+    /// Whether a hash is made with another Argon2 variant or with less memory, iterations or lanes
+    pub fn has_weaker_params(&self, parsed_hash: &PasswordHash) -> Result<bool, Error> {
+        let params = Params::try_from(parsed_hash)?;
+        Ok(parsed_hash.algorithm != Algorithm::Argon2id.ident()
+            || parsed_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost())
+    }
+
+    fn argon2(&self, with_pepper: bool) -> Result<Argon2<'_>, Error> {
+        match &self.pepper {
+            Some(pepper) if with_pepper => Argon2::new_with_secret(
+                pepper,
+                Algorithm::Argon2id,
+                Version::V0x13,
+                self.params.clone(),
+            )
+            .map_err(Error::from),
+            _ => Ok(Argon2::new(
+                Algorithm::Argon2id,
+                Version::V0x13,
+                self.params.clone(),
+            )),
+        }
+    }
+}
+
+fn parse_setting<T>(name: &str, default: T) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| format!("Invalid value of {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+pub fn get_password_hashing() -> &'static PasswordHashing {
+    &PASSWORD_HASHING
 }
 
 pub fn hash_password(password: &str) -> Result<String, Error> {
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let password_hash_string = argon2
-        .hash_password(password.as_bytes(), &salt)?
-        .to_string();
-    Ok(password_hash_string)
+    get_password_hashing().hash(password)
+}
+
+pub fn verify_password(
+    password_hash_string: &str,
+    input_password: &str,
+) -> Result<PasswordMatch, Error> {
+    get_password_hashing().verify(password_hash_string, input_password)
 }
 
-pub fn verify_password(password_hash_string: &str, input_password: &str) -> Result<(), Error> {
-    let parsed_hash = PasswordHash::new(&password_hash_string)?;
-    Argon2::default().verify_password(input_password.as_bytes(), &parsed_hash)
+pub fn get_jwt_keys() -> &'static JwtKeys {
+    &JWT_KEYS
 }
 
-pub fn get_jwt_secret_key() -> String {
-    JWT_SECRET_KEY.clone()
+pub struct AccessToken {
+    pub token: String,
+    pub jti: String,
+    pub expires_at: NaiveDateTime,
 }
 
 pub fn create_jwt_token(
     username: String,
     role: AuthRole,
-    secret_key: &str,
-) -> Result<String, jsonwebtoken::errors::Error> {
-    let exp_time = Local::now() + Duration::minutes(60);
+    keys: &JwtKeys,
+) -> Result<AccessToken, jsonwebtoken::errors::Error> {
+    let exp_time = Utc::now() + Duration::minutes(ACCESS_TOKEN_LIFETIME_MINUTES);
+    let jti = Uuid::new_v4().to_string();
 
     let claims = Claims {
         sub: username,
         exp: exp_time.timestamp(),
         role: role.to_string(),
+        permissions: role_permissions(&role)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        jti: jti.clone(),
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret_key.as_bytes()),
-    )
+    let token = keys.sign(&claims)?;
+
+    Ok(AccessToken {
+        token,
+        jti,
+        expires_at: exp_time.naive_utc(),
+    })
+}
+
+/// A random opaque token; only its hash is stored, so a leaked table can't be used to refresh
+pub fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn refresh_token_expiration(now: NaiveDateTime) -> NaiveDateTime {
+    now + Duration::days(REFRESH_TOKEN_LIFETIME_DAYS)
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::password_hash::{rand_core::OsRng, Error, PasswordHasher, SaltString};
+    use argon2::{Argon2, Params};
+
+    use super::{PasswordHashing, PasswordMatch};
+
+    const PASSWORD: &str = "password";
+
+    fn hashing(pepper: Option<&str>) -> PasswordHashing {
+        PasswordHashing {
+            params: Params::default(),
+            pepper: pepper.map(|pepper| pepper.as_bytes().to_vec()),
+        }
+    }
+
+    fn default_hash() -> String {
+        Argon2::default()
+            .hash_password(PASSWORD.as_bytes(), &SaltString::generate(&mut OsRng))
+            .expect("Can't hash password")
+            .to_string()
+    }
+
+    #[test]
+    fn peppered_hash_is_current() {
+        let hashing = hashing(Some("pepper"));
+        let hash = hashing.hash(PASSWORD).expect("Can't hash password");
+        assert_eq!(
+            PasswordMatch::Current,
+            hashing
+                .verify(&hash, PASSWORD)
+                .expect("Can't verify password")
+        );
+        assert!(matches!(
+            hashing.verify(&hash, "wrong"),
+            Err(Error::Password)
+        ));
+    }
+
+    #[test]
+    fn hash_made_before_pepper_is_outdated() {
+        let hashing = hashing(Some("pepper"));
+        let hash = default_hash();
+        assert_eq!(
+            PasswordMatch::Outdated,
+            hashing
+                .verify(&hash, PASSWORD)
+                .expect("Can't verify password")
+        );
+        assert!(matches!(
+            hashing.verify(&hash, "wrong"),
+            Err(Error::Password)
+        ));
+    }
+
+    #[test]
+    fn peppered_hash_isnt_accepted_without_pepper() {
+        let hash = hashing(Some("pepper"))
+            .hash(PASSWORD)
+            .expect("Can't hash password");
+        assert!(matches!(
+            hashing(None).verify(&hash, PASSWORD),
+            Err(Error::Password)
+        ));
+        assert!(matches!(
+            hashing(Some("another pepper")).verify(&hash, PASSWORD),
+            Err(Error::Password)
+        ));
+    }
+
+    #[test]
+    fn hash_with_weaker_params_is_outdated() {
+        let hashing = PasswordHashing {
+            params: Params::new(Params::DEFAULT_M_COST, Params::DEFAULT_T_COST + 1, 1, None)
+                .expect("Invalid Argon2 parameters"),
+            pepper: None,
+        };
+        let hash = default_hash();
+        assert_eq!(
+            PasswordMatch::Outdated,
+            hashing
+                .verify(&hash, PASSWORD)
+                .expect("Can't verify password")
+        );
+
+        let hash = hashing.hash(PASSWORD).expect("Can't hash password");
+        assert_eq!(
+            PasswordMatch::Current,
+            hashing
+                .verify(&hash, PASSWORD)
+                .expect("Can't verify password")
+        );
+    }
 }
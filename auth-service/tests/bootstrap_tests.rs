@@ -0,0 +1,81 @@
+use chrono::{Duration, Utc};
+use testcontainers::clients::Cli;
+
+use auth_service::persistence::connection::establish_migration_connection;
+use auth_service::persistence::model::{NewRefreshTokenEntity, NewUserEntity};
+use auth_service::persistence::repository::{self, AdminChange};
+
+mod common;
+
+#[actix_rt::test]
+async fn test_upsert_admin() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+    let mut conn = establish_migration_connection();
+    let now = Utc::now().naive_utc();
+
+    let admin = |hash: &str| NewUserEntity {
+        username: "admin".to_string(),
+        hash: hash.to_string(),
+        first_name: "Admin".to_string(),
+        last_name: String::new(),
+        role: "ADMIN".to_string(),
+    };
+
+    let change = repository::upsert_admin(admin("first_hash"), false, now, &mut conn)
+        .expect("Can't bootstrap admin");
+    assert_eq!(AdminChange::Created, change);
+
+    let mut async_conn = pool.get().await.expect("Can't get DB connection");
+    let admin_entity = repository::get_user("admin", &mut async_conn)
+        .await
+        .expect("Can't get admin");
+    let refresh_token = NewRefreshTokenEntity {
+        user_id: Some(admin_entity.id),
+        token_hash: "admin_token_hash".to_string(),
+        session_id: "admin_session".to_string(),
+        access_token_jti: "admin_jti".to_string(),
+        access_token_expires_at: now + Duration::minutes(15),
+        expires_at: now + Duration::days(30),
+    };
+    repository::create_refresh_token(refresh_token, &mut async_conn)
+        .await
+        .expect("Can't create refresh token");
+
+    let change = repository::upsert_admin(admin("first_hash"), false, now, &mut conn)
+        .expect("Can't bootstrap admin");
+    assert_eq!(AdminChange::Unchanged, change);
+    let token = repository::get_refresh_token("admin_token_hash", &mut async_conn)
+        .await
+        .expect("Can't get refresh token");
+    assert!(token.revoked_at.is_none());
+
+    // a password changed by the admin isn't reverted on restart
+    let change = repository::upsert_admin(admin("second_hash"), false, now, &mut conn)
+        .expect("Can't bootstrap admin");
+    assert_eq!(AdminChange::Unchanged, change);
+    let admin_entity = repository::get_user("admin", &mut async_conn)
+        .await
+        .expect("Can't get admin");
+    assert_eq!("first_hash", admin_entity.hash);
+
+    let change = repository::upsert_admin(admin("second_hash"), true, now, &mut conn)
+        .expect("Can't bootstrap admin");
+    assert_eq!(AdminChange::Updated, change);
+
+    // sessions started with the replaced password are revoked
+    let token = repository::get_refresh_token("admin_token_hash", &mut async_conn)
+        .await
+        .expect("Can't get refresh token");
+    assert!(token.revoked_at.is_some());
+
+    // other users keep their passwords
+    let admin_entity = repository::get_user("admin", &mut async_conn)
+        .await
+        .expect("Can't get admin");
+    assert_eq!("second_hash", admin_entity.hash);
+    let other_user = repository::get_user("john_doe", &mut async_conn)
+        .await
+        .expect("Can't get user");
+    assert_ne!("second_hash", other_user.hash);
+}
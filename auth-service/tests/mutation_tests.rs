@@ -1,21 +1,36 @@
+use std::env;
 use std::str;
 
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
 use actix_web::{test, web, App};
 use base64::{alphabet, engine, Engine};
 use jsonpath_lib as jsonpath;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, get_current_timestamp, Algorithm, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 use testcontainers::clients::Cli;
 
 use auth_service::{configure_service, create_schema_with_context};
 
-use common_utils::Claims;
+use common_utils::identity::{
+    verify_identity, IdentityClaims, IdentityKey, IDENTITY_HEADER, IDENTITY_TOKEN_LIFETIME_SECS,
+};
+use common_utils::{Claims, RevokedTokens};
 
 mod common;
 
+const ADMIN_PERMISSIONS: &str = "account:write,planets:write,users:read,users:write";
+const IDENTITY_SECRET: &[u8] = b"test secret which is long enough for HS256";
+const GATEWAY_ADDRESS: &str = "172.28.0.10";
+
 #[actix_rt::test]
 async fn test_sign_in() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
 
     let service = test::init_service(
         App::new()
@@ -26,7 +41,10 @@ async fn test_sign_in() {
 
     let mutation = r#"
         mutation {
-            signIn(input: { username: "john_doe", password: "password" })
+            signIn(input: { username: "john_doe", password: "password" }) {
+                accessToken
+                refreshToken
+            }
         }
         "#
     .to_string();
@@ -42,7 +60,7 @@ async fn test_sign_in() {
 
     let jwt = jsonpath::select(
         &response.data.expect("Response doesn't contain data"),
-        "$.signIn",
+        "$.signIn.accessToken",
     )
     .expect("Can't get JWT by path")
     .first()
@@ -62,7 +80,7 @@ async fn test_sign_in() {
         .decode(encoded_header)
         .expect("Can't decode Base64");
     let decoded_header_string = str::from_utf8(&decoded_header).expect("Can't convert to str");
-    let expected_header = "{\"typ\":\"JWT\",\"alg\":\"HS256\"}";
+    let expected_header = "{\"typ\":\"JWT\",\"alg\":\"RS256\",\"kid\":\"rsa-2026-10\"}";
     assert_eq!(expected_header, decoded_header_string);
 
     let encoded_payload = &jwt[(first_dot_index + 1)..last_dot_index];
@@ -74,12 +92,47 @@ async fn test_sign_in() {
         serde_json::from_str(decoded_payload_string).expect("Can't deserialize claims");
     assert_eq!("john_doe", &claims.sub);
     assert_eq!("ADMIN", &claims.role);
+    // an admin is granted permissions of a user too
+    assert!(claims.permissions.contains(&"users:write".to_string()));
+    assert!(claims.permissions.contains(&"account:write".to_string()));
+    assert!(!claims.jti.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_token_is_verified_with_jwks() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let (access_token, _) = sign_in(&service).await;
+
+    let request = test::TestRequest::get()
+        .uri("/.well-known/jwks.json")
+        .to_request();
+    let jwks: JwkSet = test::call_and_read_body_json(&service, request).await;
+    assert_eq!(2, jwks.keys.len());
+
+    let jwk = jwks.find("rsa-2026-10").expect("Can't find signing key");
+    let decoding_key = DecodingKey::from_jwk(jwk).expect("Can't create decoding key");
+    let token_data = decode::<Claims>(
+        &access_token,
+        &decoding_key,
+        &Validation::new(Algorithm::RS256),
+    )
+    .expect("Can't verify JWT");
+    assert_eq!("john_doe", &token_data.claims.sub);
 }
 
 #[actix_rt::test]
 async fn test_sign_in_fails() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
 
     let service = test::init_service(
         App::new()
@@ -90,7 +143,9 @@ async fn test_sign_in_fails() {
 
     let mutation = r#"
         mutation {
-            signIn(input: { username: "john_doe", password: "wrong_password" })
+            signIn(input: { username: "john_doe", password: "wrong_password" }) {
+                accessToken
+            }
         }
         "#
     .to_string();
@@ -104,18 +159,571 @@ async fn test_sign_in_fails() {
 
     let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
 
-    let error_message = jsonpath::select(
-        &response.errors.expect("Response doesn't contain errors"),
-        "$[0].message",
+    let errors = response.errors.expect("Response doesn't contain errors");
+
+    let error_message = jsonpath::select(&errors, "$[0].message")
+        .expect("Can't get error message by path")
+        .first()
+        .expect("Can't get error message")
+        .as_str()
+        .expect("Can't get error message")
+        .to_string();
+
+    assert_eq!("Invalid username or password", error_message);
+
+    let error_code =
+        jsonpath::select(&errors, "$[0].extensions.code").expect("Can't get error code by path")[0];
+    assert_eq!("FORBIDDEN", error_code);
+}
+
+#[actix_rt::test]
+async fn test_sign_in_lockout() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .wrap(from_fn(verify_identity))
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool)))
+            .app_data(web::Data::new(identity_key())),
+    )
+    .await;
+
+    let mutation = r#"
+        mutation {
+            signIn(input: { username: "john_doe", password: "wrong" }) {
+                accessToken
+            }
+        }
+        "#;
+    for _ in 0..5 {
+        let response = execute(&service, mutation.to_string()).await;
+        assert_eq!("FORBIDDEN", get_error_code(&response));
+    }
+
+    // even the correct password is rejected while the user is locked
+    let mutation = r#"
+        mutation {
+            signIn(input: { username: "john_doe", password: "password" }) {
+                accessToken
+            }
+        }
+        "#;
+    let response = execute(&service, mutation.to_string()).await;
+    assert_eq!("TOO_MANY_REQUESTS", get_error_code(&response));
+
+    let unlock_mutation = r#"
+        mutation {
+            unlockUser(username: "john_doe")
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        unlock_mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "admin",
+    )
+    .await;
+    assert!(response.errors.is_none());
+
+    let response = execute(&service, mutation.to_string()).await;
+    assert!(response.errors.is_none());
+}
+
+#[actix_rt::test]
+async fn test_sign_in_lockout_per_ip_ignores_spoofed_forwarded_for() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+    env::set_var("TRUSTED_PROXIES", GATEWAY_ADDRESS);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    // every attempt uses another username, so only the per-IP counter can lock the client out;
+    // the client prepends a new address each time, the gateway appends the real one
+    let sign_in_request = |attempt: i32| {
+        let mutation = format!(
+            r#"
+            mutation {{
+                signIn(input: {{ username: "unknown_{}", password: "wrong" }}) {{
+                    accessToken
+                }}
+            }}
+            "#,
+            attempt
+        );
+        test::TestRequest::post()
+            .uri("/")
+            .peer_addr(format!("{}:4000", GATEWAY_ADDRESS).parse().unwrap())
+            .insert_header((
+                "x-forwarded-for",
+                format!("10.0.0.{}, 203.0.113.7", attempt),
+            ))
+            .set_json(&GraphQLCustomRequest { query: mutation })
+            .to_request()
+    };
+    for attempt in 0..20 {
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, sign_in_request(attempt)).await;
+        assert_eq!("FORBIDDEN", get_error_code(&response));
+    }
+
+    let response: GraphQLCustomResponse =
+        test::call_and_read_body_json(&service, sign_in_request(20)).await;
+    assert_eq!("TOO_MANY_REQUESTS", get_error_code(&response));
+}
+
+#[actix_rt::test]
+async fn test_refresh_token() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let (_, refresh_token) = sign_in(&service).await;
+
+    let response = refresh(&service, &refresh_token).await;
+    let new_refresh_token = get_string(&response, "$.refreshToken.refreshToken");
+    assert_ne!(refresh_token, new_refresh_token);
+
+    // reuse of a refresh token revokes the whole session
+    let response = refresh(&service, &refresh_token).await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+
+    let response = refresh(&service, &new_refresh_token).await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+}
+
+#[actix_rt::test]
+async fn test_sign_out() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone())))
+            .app_data(web::Data::new(pool)),
+    )
+    .await;
+
+    let (access_token, refresh_token) = sign_in(&service).await;
+    let (other_access_token, other_refresh_token) = sign_in(&service).await;
+
+    let mutation = format!(
+        r#"
+        mutation {{
+            signOut(refreshToken: "{}")
+        }}
+        "#,
+        refresh_token
+    );
+    let response = execute(&service, mutation).await;
+    assert!(response.errors.is_none());
+
+    let response = refresh(&service, &refresh_token).await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+
+    let request = test::TestRequest::get().uri("/revoked-tokens").to_request();
+    let revoked_tokens: RevokedTokens = test::call_and_read_body_json(&service, request).await;
+    assert_eq!(vec![get_jti(&access_token)], revoked_tokens.jtis);
+
+    // the other session isn't affected until all sessions are revoked
+    let mutation = format!(
+        r#"
+        mutation {{
+            revokeAllSessions(refreshToken: "{}")
+        }}
+        "#,
+        other_refresh_token
+    );
+    let response = execute(&service, mutation).await;
+    assert!(response.errors.is_none());
+
+    let request = test::TestRequest::get().uri("/revoked-tokens").to_request();
+    let revoked_tokens: RevokedTokens = test::call_and_read_body_json(&service, request).await;
+    assert!(revoked_tokens.jtis.contains(&get_jti(&other_access_token)));
+}
+
+#[actix_rt::test]
+async fn test_user_management() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .wrap(from_fn(verify_identity))
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool)))
+            .app_data(web::Data::new(identity_key())),
+    )
+    .await;
+
+    let query = r#"
+        {
+            getUsers(first: 1) {
+                nodes {
+                    username
+                }
+            }
+        }
+        "#;
+    let response = execute(&service, query.to_string()).await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+
+    let response = execute_as(&service, query.to_string(), ADMIN_PERMISSIONS, "john_doe").await;
+    assert_eq!(
+        "john_doe",
+        get_string(&response, "$.getUsers.nodes[0].username")
+    );
+
+    let mutation = r#"
+        mutation {
+            createUser(user: {
+                username: "jane_doe", password: "password", firstName: "Jane", lastName: "Doe", role: USER
+            }) {
+                username
+            }
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
+    )
+    .await;
+    assert!(response.errors.is_none());
+
+    let mutation = r#"
+        mutation {
+            updateUser(username: "jane_doe", user: { lastName: "Roe" }) {
+                firstName
+                lastName
+            }
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
+    )
+    .await;
+    assert_eq!("Jane", get_string(&response, "$.updateUser.firstName"));
+    assert_eq!("Roe", get_string(&response, "$.updateUser.lastName"));
+
+    let mutation = r#"
+        mutation {
+            changeRole(username: "jane_doe", role: ADMIN) {
+                role
+            }
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
+    )
+    .await;
+    assert_eq!("ADMIN", get_string(&response, "$.changeRole.role"));
+
+    // an admin can't demote or delete themselves
+    let mutation = r#"
+        mutation {
+            deleteUser(username: "john_doe") {
+                username
+            }
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
+    )
+    .await;
+    assert_eq!("BAD_USER_INPUT", get_error_code(&response));
+
+    let mutation = r#"
+        mutation {
+            deleteUser(username: "jane_doe") {
+                username
+            }
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
     )
-    .expect("Can't get error message by path")
+    .await;
+    assert_eq!("jane_doe", get_string(&response, "$.deleteUser.username"));
+
+    let query = r#"
+        {
+            getUser(username: "jane_doe") {
+                username
+            }
+        }
+        "#;
+    let response = execute_as(&service, query.to_string(), ADMIN_PERMISSIONS, "john_doe").await;
+    let data = response.data.expect("Response doesn't contain data");
+    assert!(data["getUser"].is_null());
+}
+
+#[actix_rt::test]
+async fn test_change_my_password() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .wrap(from_fn(verify_identity))
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool)))
+            .app_data(web::Data::new(identity_key())),
+    )
+    .await;
+
+    let (_, refresh_token) = sign_in(&service).await;
+
+    let mutation = r#"
+        mutation {
+            changeMyPassword(currentPassword: "wrong", newPassword: "new_password")
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
+    )
+    .await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+
+    let mutation = r#"
+        mutation {
+            changeMyPassword(currentPassword: "password", newPassword: "new_password")
+        }
+        "#;
+    let response = execute_as(
+        &service,
+        mutation.to_string(),
+        ADMIN_PERMISSIONS,
+        "john_doe",
+    )
+    .await;
+    assert!(response.errors.is_none());
+
+    // sessions started with the old password are revoked
+    let response = refresh(&service, &refresh_token).await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+
+    let mutation = r#"
+        mutation {
+            signIn(input: { username: "john_doe", password: "new_password" }) {
+                accessToken
+            }
+        }
+        "#;
+    let response = execute(&service, mutation.to_string()).await;
+    assert!(response.errors.is_none());
+}
+
+#[actix_rt::test]
+async fn test_unverified_identity_is_rejected() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .wrap(from_fn(verify_identity))
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool)))
+            .app_data(web::Data::new(identity_key())),
+    )
+    .await;
+
+    let query = r#"
+        query {
+            getUsers(first: 1) {
+                nodes {
+                    username
+                }
+            }
+        }
+        "#;
+
+    // the headers which subgraphs trusted before identities were signed
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("role", "ADMIN"))
+        .insert_header(("permissions", ADMIN_PERMISSIONS))
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+        })
+        .to_request();
+    let response = test::call_service(&service, request).await;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let forged_key = IdentityKey::new(b"forged secret which is long enough for HS256")
+        .expect("Can't create identity key");
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header((
+            IDENTITY_HEADER,
+            sign_identity(&forged_key, ADMIN_PERMISSIONS, "john_doe"),
+        ))
+        .set_json(&GraphQLCustomRequest {
+            query: query.to_string(),
+        })
+        .to_request();
+    let response = test::call_service(&service, request).await;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    // an anonymous request passes the middleware, but isn't allowed to read users
+    let response = execute(&service, query.to_string()).await;
+    assert_eq!("FORBIDDEN", get_error_code(&response));
+}
+
+async fn sign_in<S, B>(service: &S) -> (String, String)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let mutation = r#"
+        mutation {
+            signIn(input: { username: "john_doe", password: "password" }) {
+                accessToken
+                refreshToken
+            }
+        }
+        "#
+    .to_string();
+    let response = execute(service, mutation).await;
+    (
+        get_string(&response, "$.signIn.accessToken"),
+        get_string(&response, "$.signIn.refreshToken"),
+    )
+}
+
+async fn refresh<S, B>(service: &S, refresh_token: &str) -> GraphQLCustomResponse
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let mutation = format!(
+        r#"
+        mutation {{
+            refreshToken(refreshToken: "{}") {{
+                accessToken
+                refreshToken
+            }}
+        }}
+        "#,
+        refresh_token
+    );
+    execute(service, mutation).await
+}
+
+async fn execute<S, B>(service: &S, query: String) -> GraphQLCustomResponse
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&GraphQLCustomRequest { query })
+        .to_request();
+    test::call_and_read_body_json(service, request).await
+}
+
+/// Executes a query with an identity which the gateway signs for a signed-in user
+async fn execute_as<S, B>(
+    service: &S,
+    query: String,
+    permissions: &str,
+    username: &str,
+) -> GraphQLCustomResponse
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header((
+            IDENTITY_HEADER,
+            sign_identity(&identity_key(), permissions, username),
+        ))
+        .set_json(&GraphQLCustomRequest { query })
+        .to_request();
+    test::call_and_read_body_json(service, request).await
+}
+
+fn identity_key() -> IdentityKey {
+    IdentityKey::new(IDENTITY_SECRET).expect("Can't create identity key")
+}
+
+fn sign_identity(key: &IdentityKey, permissions: &str, username: &str) -> String {
+    let claims = IdentityClaims {
+        sub: username.to_string(),
+        role: "ADMIN".to_string(),
+        permissions: permissions.split(',').map(str::to_string).collect(),
+        exp: get_current_timestamp() + IDENTITY_TOKEN_LIFETIME_SECS,
+    };
+    key.sign(&claims).expect("Can't sign identity")
+}
+
+fn get_string(response: &GraphQLCustomResponse, path: &str) -> String {
+    jsonpath::select(
+        response
+            .data
+            .as_ref()
+            .expect("Response doesn't contain data"),
+        path,
+    )
+    .expect("Can't get value by path")
     .first()
-    .expect("Can't get error message")
+    .expect("Can't get value")
     .as_str()
-    .expect("Can't get error message")
-    .to_string();
+    .expect("Value isn't a string")
+    .to_string()
+}
 
-    assert_eq!("invalid password", error_message);
+fn get_error_code(response: &GraphQLCustomResponse) -> String {
+    let errors = response
+        .errors
+        .as_ref()
+        .expect("Response doesn't contain errors");
+    jsonpath::select(errors, "$[0].extensions.code").expect("Can't get error code by path")[0]
+        .as_str()
+        .expect("Error code isn't a string")
+        .to_string()
+}
+
+fn get_jti(jwt: &str) -> String {
+    let encoded_payload = jwt.split('.').nth(1).expect("Incorrect JWT");
+    let base64_engine =
+        engine::GeneralPurpose::new(&alphabet::URL_SAFE, engine::general_purpose::NO_PAD);
+    let decoded_payload = base64_engine
+        .decode(encoded_payload)
+        .expect("Can't decode Base64");
+    let claims: Claims =
+        serde_json::from_slice(&decoded_payload).expect("Can't deserialize claims");
+    claims.jti
 }
 
 #[derive(Serialize)]
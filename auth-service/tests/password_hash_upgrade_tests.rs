@@ -0,0 +1,100 @@
+use std::env;
+
+use actix_web::{test, web, App};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, SaltString};
+use argon2::{Argon2, Params, PasswordVerifier};
+use jsonpath_lib as jsonpath;
+use serde::{Deserialize, Serialize};
+use testcontainers::clients::Cli;
+
+use auth_service::persistence::model::NewUserEntity;
+use auth_service::persistence::repository;
+use auth_service::{configure_service, create_schema_with_context};
+
+mod common;
+
+// hashing is configured once per process, so these tests are kept apart from the others
+#[actix_rt::test]
+async fn test_outdated_hash_is_upgraded_on_sign_in() {
+    env::set_var("PASSWORD_PEPPER", "test pepper");
+    env::set_var(
+        "ARGON2_ITERATIONS",
+        (Params::DEFAULT_T_COST + 1).to_string(),
+    );
+
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    // made before the pepper and stronger parameters were configured
+    let old_hash = Argon2::default()
+        .hash_password(b"password", &SaltString::generate(&mut OsRng))
+        .expect("Can't hash password")
+        .to_string();
+    let mut conn = pool.get().await.expect("Can't get DB connection");
+    repository::create(
+        NewUserEntity {
+            username: "legacy_user".to_string(),
+            hash: old_hash.clone(),
+            first_name: "Legacy".to_string(),
+            last_name: "User".to_string(),
+            role: "USER".to_string(),
+        },
+        &mut conn,
+    )
+    .await
+    .expect("Can't create user");
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone()))),
+    )
+    .await;
+
+    let mutation = r#"
+        mutation {
+            signIn(input: { username: "legacy_user", password: "password" }) {
+                accessToken
+            }
+        }
+        "#;
+    for _ in 0..2 {
+        let request = test::TestRequest::post()
+            .uri("/")
+            .set_json(&GraphQLCustomRequest {
+                query: mutation.to_string(),
+            })
+            .to_request();
+        let response: GraphQLCustomResponse =
+            test::call_and_read_body_json(&service, request).await;
+        assert!(response.errors.is_none());
+        let data = response.data.expect("Response doesn't contain data");
+        let access_token = jsonpath::select(&data, "$.signIn.accessToken")
+            .expect("Can't get access token by path");
+        assert!(!access_token.is_empty());
+    }
+
+    let new_hash = repository::get_user("legacy_user", &mut conn)
+        .await
+        .expect("Can't get user")
+        .hash;
+    assert_ne!(old_hash, new_hash);
+    let parsed_hash = PasswordHash::new(&new_hash).expect("Can't parse hash");
+    let params = Params::try_from(&parsed_hash).expect("Can't get hash parameters");
+    assert_eq!(Params::DEFAULT_T_COST + 1, params.t_cost());
+    // the new hash is peppered, so it can't be verified without the pepper
+    assert!(Argon2::default()
+        .verify_password(b"password", &parsed_hash)
+        .is_err());
+}
+
+#[derive(Serialize)]
+struct GraphQLCustomRequest {
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct GraphQLCustomResponse {
+    data: Option<serde_json::Value>,
+    errors: Option<serde_json::Value>,
+}
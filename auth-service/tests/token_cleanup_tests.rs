@@ -0,0 +1,59 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use testcontainers::clients::Cli;
+
+use auth_service::persistence::model::NewRefreshTokenEntity;
+use auth_service::persistence::repository;
+
+mod common;
+
+#[actix_rt::test]
+async fn test_stale_refresh_tokens_are_deleted() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+    let mut conn = pool.get().await.expect("Can't get DB connection");
+    let now = Utc::now().naive_utc();
+
+    let user = repository::get_user("john_doe", &mut conn)
+        .await
+        .expect("Can't get user");
+    let token = |name: &str, issued_at: NaiveDateTime| NewRefreshTokenEntity {
+        user_id: Some(user.id),
+        token_hash: name.to_string(),
+        session_id: name.to_string(),
+        access_token_jti: name.to_string(),
+        access_token_expires_at: issued_at + Duration::minutes(15),
+        expires_at: issued_at + Duration::days(30),
+    };
+    for (name, issued_at) in [
+        ("expired", now - Duration::days(31)),
+        ("revoked", now - Duration::hours(1)),
+        ("recently_revoked", now),
+        ("used", now - Duration::days(29)),
+    ] {
+        repository::create_refresh_token(token(name, issued_at), &mut conn)
+            .await
+            .expect("Can't create refresh token");
+    }
+    for name in ["revoked", "recently_revoked"] {
+        repository::revoke_session(name, now, &mut conn)
+            .await
+            .expect("Can't revoke session");
+    }
+    let used_token = repository::get_refresh_token("used", &mut conn)
+        .await
+        .expect("Can't get refresh token");
+    repository::mark_refresh_token_used(used_token.id, now, &mut conn)
+        .await
+        .expect("Can't mark refresh token as used");
+
+    let deleted = repository::delete_stale_refresh_tokens(now - Duration::minutes(30), &mut conn)
+        .await
+        .expect("Can't delete refresh tokens");
+    assert_eq!(2, deleted);
+
+    // access tokens of a recently revoked session are still reported to the gateway,
+    // and a reuse of a used token is still detected
+    for name in ["recently_revoked", "used"] {
+        assert!(repository::get_refresh_token(name, &mut conn).await.is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+use std::cell::Cell;
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use deadpool::Runtime;
+use diesel_async::pooled_connection::deadpool::{Object, Pool, PoolError};
+use diesel_async::pooled_connection::{
+    AsyncDieselConnectionManager, ManagerConfig, RecyclingMethod,
+};
+use diesel_async::AsyncPgConnection;
+use futures::future::try_join_all;
+use tracing::warn;
+
+use crate::metrics;
+
+const INITIAL_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct PoolConfig {
+    pub max_size: usize,
+    /// Connections opened at startup and kept open even if they are idle
+    pub min_size: usize,
+    /// How long to wait for a free connection or for a new one to be opened
+    pub connection_timeout: Duration,
+    /// Connections unused for longer are closed; `None` keeps them open
+    pub idle_timeout: Option<Duration>,
+    /// Whether a connection is checked with a query before it's handed out
+    pub test_on_checkout: bool,
+    /// How many times to try to connect at startup before giving up
+    pub connect_attempts: u32,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 10,
+            min_size: 1,
+            connection_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+            test_on_checkout: true,
+            connect_attempts: 10,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads `DB_POOL_*` and `DB_CONNECT_ATTEMPTS` variables; unset ones keep default values.
+    /// `DB_POOL_IDLE_TIMEOUT_SECS=0` disables closing of idle connections
+    pub fn from_env() -> Result<Self, String> {
+        let default = PoolConfig::default();
+        let config = PoolConfig {
+            max_size: parse_env("DB_POOL_MAX_SIZE")?.unwrap_or(default.max_size),
+            min_size: parse_env("DB_POOL_MIN_SIZE")?.unwrap_or(default.min_size),
+            connection_timeout: parse_env("DB_POOL_CONNECTION_TIMEOUT_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(default.connection_timeout),
+            idle_timeout: match parse_env("DB_POOL_IDLE_TIMEOUT_SECS")? {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => default.idle_timeout,
+            },
+            test_on_checkout: parse_env("DB_POOL_TEST_ON_CHECKOUT")?
+                .unwrap_or(default.test_on_checkout),
+            connect_attempts: parse_env("DB_CONNECT_ATTEMPTS")?.unwrap_or(default.connect_attempts),
+        };
+
+        if config.max_size == 0 {
+            return Err("DB_POOL_MAX_SIZE must be positive".to_string());
+        }
+        if config.min_size > config.max_size {
+            return Err("DB_POOL_MIN_SIZE can't be greater than DB_POOL_MAX_SIZE".to_string());
+        }
+        if config.connect_attempts == 0 {
+            return Err("DB_CONNECT_ATTEMPTS must be positive".to_string());
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_env<T>(name: &str) -> Result<Option<T>, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("Invalid value of {}: {}", name, e)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Connection pool that records how long clients wait for a connection
+#[derive(Clone)]
+pub struct PgPool {
+    pool: Pool<AsyncPgConnection>,
+}
+
+/// Snapshot of a pool's state, exposed as metrics
+pub struct PoolStats {
+    pub max_size: usize,
+    pub size: usize,
+    pub in_use: usize,
+    pub idle: usize,
+    /// Clients waiting for a connection right now
+    pub waiting: usize,
+}
+
+impl PgPool {
+    pub async fn get(&self) -> Result<Object<AsyncPgConnection>, PoolError> {
+        let started_at = Instant::now();
+        let result = self.pool.get().await;
+        metrics::observe_pool_wait(
+            started_at.elapsed(),
+            matches!(result, Err(PoolError::Timeout(_))),
+        );
+
+        result
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let status = self.pool.status();
+        PoolStats {
+            max_size: status.max_size,
+            size: status.size,
+            in_use: status.size.saturating_sub(status.available),
+            idle: status.available,
+            waiting: status.waiting,
+        }
+    }
+
+    /// Opens `count` connections at once, so they are ready before the first request
+    async fn warm_up(&self, count: usize) -> Result<(), PoolError> {
+        try_join_all((0..count).map(|_| self.pool.get())).await?;
+        Ok(())
+    }
+
+    fn close_idle_connections(&self, idle_timeout: Duration, min_size: usize) {
+        let closable = Cell::new(self.pool.status().size.saturating_sub(min_size));
+        self.pool.retain(|_, metrics| {
+            if closable.get() > 0 && metrics.last_used() > idle_timeout {
+                closable.set(closable.get() - 1);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Creates a pool and waits until the database accepts connections,
+/// retrying with exponential backoff so a service can start before the database
+pub async fn create_pool(database_url: &str, config: &PoolConfig) -> Result<PgPool, String> {
+    // `ManagerConfig` is non-exhaustive, so it can't be built with a struct expression
+    let mut manager_config = ManagerConfig::default();
+    manager_config.recycling_method = if config.test_on_checkout {
+        RecyclingMethod::Verified
+    } else {
+        RecyclingMethod::Fast
+    };
+    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new_with_config(
+        database_url,
+        manager_config,
+    );
+    let pool = Pool::builder(manager)
+        .max_size(config.max_size)
+        .wait_timeout(Some(config.connection_timeout))
+        .create_timeout(Some(config.connection_timeout))
+        .recycle_timeout(Some(config.connection_timeout))
+        .runtime(Runtime::Tokio1)
+        .build()
+        .map_err(|e| format!("Failed to create pool: {}", e))?;
+    let pool = PgPool { pool };
+
+    let mut retry_delay = INITIAL_CONNECT_RETRY_DELAY;
+    for attempt in 1..=config.connect_attempts {
+        // at least one connection is opened to make sure the database is reachable
+        match pool.warm_up(config.min_size.max(1)).await {
+            Ok(_) => break,
+            Err(e) if attempt < config.connect_attempts => {
+                warn!(
+                    error = %e,
+                    attempt,
+                    max_attempts = config.connect_attempts,
+                    "Can't connect to DB, retrying in {:?}",
+                    retry_delay
+                );
+                actix_rt::time::sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(MAX_CONNECT_RETRY_DELAY);
+            }
+            Err(e) => return Err(format!("Can't connect to DB: {}", e)),
+        }
+    }
+
+    if let Some(idle_timeout) = config.idle_timeout {
+        start_idle_connection_reaper(pool.clone(), idle_timeout, config.min_size);
+    }
+
+    Ok(pool)
+}
+
+fn start_idle_connection_reaper(pool: PgPool, idle_timeout: Duration, min_size: usize) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(idle_timeout.min(MAX_IDLE_CHECK_INTERVAL));
+        loop {
+            interval.tick().await;
+            pool.close_idle_connections(idle_timeout, min_size);
+        }
+    });
+}
@@ -0,0 +1,144 @@
+use std::num::ParseIntError;
+
+use async_graphql::{Error, ErrorExtensions, ServerError, ID};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel_async::pooled_connection::deadpool::PoolError;
+use strum_macros::Display;
+use tracing::error;
+
+/// Exposed to clients as `extensions.code` of a GraphQL error
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Display)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadUserInput,
+    NotFound,
+    Forbidden,
+    Internal,
+    Unavailable,
+    TooManyRequests,
+    PersistedQueryNotFound,
+    PersistedQueryNotSupported,
+    PersistedQueryNotInList,
+}
+
+/// Error of a resolver that is converted into a GraphQL error with a code.
+///
+/// It doesn't implement `Display` on purpose: otherwise async-graphql's blanket
+/// conversion would be used, and the code would be lost
+#[derive(Clone, Debug)]
+pub struct ServiceError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+pub type ServiceResult<T> = Result<T, ServiceError>;
+
+impl ServiceError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_user_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadUserInput, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Forbidden, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unavailable, message)
+    }
+}
+
+impl From<ServiceError> for Error {
+    fn from(error: ServiceError) -> Self {
+        Error::new(error.message).extend_with(|_, extensions| {
+            extensions.set("code", error.code.to_string());
+        })
+    }
+}
+
+/// Errors which happen before execution, for example, in an extension
+impl From<ServiceError> for ServerError {
+    fn from(error: ServiceError) -> Self {
+        let error = Error::from(error);
+        let mut server_error = ServerError::new(error.message, None);
+        server_error.extensions = error.extensions;
+        server_error
+    }
+}
+
+/// Errors produced by async-graphql itself, for example, a missing context data
+impl From<Error> for ServiceError {
+    fn from(error: Error) -> Self {
+        ServiceError::internal(error.message)
+    }
+}
+
+impl From<DieselError> for ServiceError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DieselError::NotFound => ServiceError::not_found("Not found"),
+            DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation
+                | DatabaseErrorKind::ForeignKeyViolation
+                | DatabaseErrorKind::NotNullViolation
+                | DatabaseErrorKind::CheckViolation,
+                info,
+            ) => ServiceError::bad_user_input(info.message()),
+            DieselError::DatabaseError(DatabaseErrorKind::ClosedConnection, _) => {
+                ServiceError::unavailable("Database is unavailable")
+            }
+            // details of other errors aren't exposed to clients
+            e => {
+                error!(error = %e, "Database error");
+                ServiceError::internal("Internal server error")
+            }
+        }
+    }
+}
+
+impl From<PoolError> for ServiceError {
+    fn from(error: PoolError) -> Self {
+        error!(%error, "Can't get DB connection");
+        ServiceError::unavailable("Database is unavailable")
+    }
+}
+
+impl From<ParseIntError> for ServiceError {
+    fn from(error: ParseIntError) -> Self {
+        ServiceError::bad_user_input(error.to_string())
+    }
+}
+
+/// Entities are identified by integer primary keys
+pub fn parse_id(id: &ID) -> ServiceResult<i32> {
+    id.parse::<i32>()
+        .map_err(|_| ServiceError::bad_user_input(format!("Invalid ID: {}", id.as_str())))
+}
+
+/// Sets a code on an error that doesn't have one yet, for example,
+/// an error raised by async-graphql while parsing connection arguments
+pub fn with_default_code(error: Error, code: ErrorCode) -> Error {
+    let has_code = error
+        .extensions
+        .as_ref()
+        .is_some_and(|extensions| extensions.get("code").is_some());
+    if has_code {
+        error
+    } else {
+        error.extend_with(|_, extensions| extensions.set("code", code.to_string()))
+    }
+}
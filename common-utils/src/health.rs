@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use diesel_async::SimpleAsyncConnection;
+use serde::Serialize;
+
+use crate::db::PgPool;
+
+// a readiness probe should answer before an orchestrator gives up on it
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyHealth {
+    /// Runs a check, treating one that doesn't finish in time as failed
+    pub async fn check<F, E>(check: F) -> Self
+    where
+        F: Future<Output = Result<(), E>>,
+        E: ToString,
+    {
+        match actix_rt::time::timeout(CHECK_TIMEOUT, check).await {
+            Ok(Ok(_)) => DependencyHealth {
+                status: HealthStatus::Up,
+                error: None,
+            },
+            Ok(Err(e)) => DependencyHealth {
+                status: HealthStatus::Down,
+                error: Some(e.to_string()),
+            },
+            Err(_) => DependencyHealth {
+                status: HealthStatus::Down,
+                error: Some(format!("No response in {:?}", CHECK_TIMEOUT)),
+            },
+        }
+    }
+}
+
+/// Service is up only if all of its dependencies are up
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub checks: BTreeMap<&'static str, DependencyHealth>,
+}
+
+impl HealthReport {
+    pub fn new(checks: BTreeMap<&'static str, DependencyHealth>) -> Self {
+        let status = if checks.values().all(|c| c.status == HealthStatus::Up) {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        };
+        HealthReport { status, checks }
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        match self.status {
+            HealthStatus::Up => HttpResponse::Ok().json(self),
+            HealthStatus::Down => HttpResponse::ServiceUnavailable().json(self),
+        }
+    }
+}
+
+pub async fn check_database(pool: &PgPool) -> DependencyHealth {
+    DependencyHealth::check(async {
+        let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+        conn.batch_execute("SELECT 1")
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// The process is able to handle requests; dependencies aren't checked,
+/// so their outage doesn't get the service restarted
+pub async fn live() -> HttpResponse {
+    HealthReport::new(BTreeMap::new()).into_response()
+}
+
+/// Readiness of a service which depends only on its database
+pub async fn ready(pool: web::Data<PgPool>) -> HttpResponse {
+    HealthReport::new(BTreeMap::from([("database", check_database(&pool).await)])).into_response()
+}
@@ -0,0 +1,117 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::permissions::Permissions;
+
+/// Header which the gateway sets to a token signed with [`IdentityKey`]
+pub const IDENTITY_HEADER: &str = "x-internal-identity";
+
+/// Identity tokens are issued per request, so they are valid only while it's executed
+pub const IDENTITY_TOKEN_LIFETIME_SECS: u64 = 60;
+
+// HS256 keys shorter than the hash output are weak
+const MIN_SECRET_LENGTH: usize = 32;
+
+// headers which carried an identity before it was signed; a subgraph rejects them,
+// so a misconfigured router or a direct caller can't rely on them
+const UNSIGNED_IDENTITY_HEADERS: [&str; 3] = ["role", "permissions", "username"];
+
+/// Claims of a token which the gateway issues after it verifies a user's JWT
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IdentityClaims {
+    pub sub: String,
+    pub role: String,
+    pub permissions: Vec<String>,
+    pub exp: u64,
+}
+
+/// Secret shared by the gateway and subgraphs, so only the gateway can assert who a user is
+#[derive(Clone)]
+pub struct IdentityKey {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+}
+
+impl IdentityKey {
+    /// Reads `INTERNAL_IDENTITY_SECRET`
+    pub fn from_env() -> Result<Self, String> {
+        let secret = std::env::var("INTERNAL_IDENTITY_SECRET")
+            .map_err(|_| "Can't read INTERNAL_IDENTITY_SECRET")?;
+        Self::new(secret.as_bytes())
+    }
+
+    pub fn new(secret: &[u8]) -> Result<Self, String> {
+        if secret.len() < MIN_SECRET_LENGTH {
+            return Err(format!(
+                "Internal identity secret must be at least {} bytes long",
+                MIN_SECRET_LENGTH
+            ));
+        }
+        Ok(IdentityKey {
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+        })
+    }
+
+    pub fn sign(&self, claims: &IdentityClaims) -> jsonwebtoken::errors::Result<String> {
+        encode(&Header::new(Algorithm::HS256), claims, &self.encoding_key)
+    }
+
+    pub fn verify(&self, token: &str) -> jsonwebtoken::errors::Result<IdentityClaims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        decode::<IdentityClaims>(token, &self.decoding_key, &validation).map(|data| data.claims)
+    }
+}
+
+/// Signed-in user whose identity is verified by [`verify_identity`]
+#[derive(Clone)]
+pub struct Identity {
+    pub username: String,
+    pub role: String,
+    pub permissions: Permissions,
+}
+
+/// Middleware which verifies the `x-internal-identity` header with the [`IdentityKey`]
+/// from app data and adds the [`Identity`] to request extensions before a GraphQL request
+/// is executed. A request without the header is anonymous; a request with an invalid token
+/// or with unsigned `role`, `permissions` or `username` headers is rejected
+pub async fn verify_identity(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if UNSIGNED_IDENTITY_HEADERS
+        .iter()
+        .any(|name| req.headers().contains_key(*name))
+    {
+        return Err(ErrorUnauthorized(
+            "Unsigned identity headers aren't accepted",
+        ));
+    }
+
+    if let Some(header_value) = req.headers().get(IDENTITY_HEADER) {
+        let key = req
+            .app_data::<web::Data<IdentityKey>>()
+            .ok_or_else(|| ErrorInternalServerError("Internal identity key isn't configured"))?;
+        let token = header_value
+            .to_str()
+            .map_err(|_| ErrorUnauthorized("Invalid identity token"))?;
+        let claims = key.verify(token).map_err(|e| {
+            warn!(error = %e, "Invalid identity token");
+            ErrorUnauthorized("Invalid identity token")
+        })?;
+        req.extensions_mut().insert(Identity {
+            username: claims.sub,
+            role: claims.role,
+            permissions: claims.permissions.into_iter().collect(),
+        });
+    }
+
+    next.call(req).await
+}
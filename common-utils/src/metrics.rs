@@ -0,0 +1,250 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextResolve, ResolveInfo,
+};
+use async_graphql::{Response, ServerResult, Value};
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::db::PgPool;
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("http_requests_total", "Number of HTTP requests"),
+        &["method", "path", "status"],
+    ))
+});
+
+static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "Time spent on processing of an HTTP request",
+        ),
+        &["method", "path"],
+    ))
+});
+
+static GRAPHQL_OPERATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "graphql_operations_total",
+            "Number of executed GraphQL operations by their root fields",
+        ),
+        &["root_field", "status"],
+    ))
+});
+
+static GRAPHQL_RESOLVER_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "graphql_resolver_duration_seconds",
+            "Time spent in a field resolver",
+        )
+        .buckets(exponential_buckets(0.0001, 4.0, 10).expect("Invalid buckets")),
+        &["parent_type", "field"],
+    ))
+});
+
+static DATALOADER_BATCH_SIZE: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "dataloader_batch_size",
+            "Number of keys loaded by a DataLoader at once",
+        )
+        .buckets(exponential_buckets(1.0, 2.0, 8).expect("Invalid buckets")),
+        &["loader"],
+    ))
+});
+
+static DB_POOL_CONNECTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new("db_pool_connections", "Number of open DB connections"),
+        &["state"],
+    ))
+});
+
+static DB_POOL_MAX_SIZE: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new(
+        "db_pool_max_size",
+        "Maximum number of DB connections",
+    ))
+});
+
+static DB_POOL_WAITING: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new(
+        "db_pool_waiting",
+        "Number of clients waiting for a DB connection",
+    ))
+});
+
+static DB_POOL_WAIT: LazyLock<Histogram> = LazyLock::new(|| {
+    register(Histogram::with_opts(
+        HistogramOpts::new(
+            "db_pool_wait_seconds",
+            "Time spent waiting for a DB connection",
+        )
+        .buckets(exponential_buckets(0.0005, 4.0, 9).expect("Invalid buckets")),
+    ))
+});
+
+static DB_POOL_TIMEOUTS: LazyLock<IntCounter> = LazyLock::new(|| {
+    register(IntCounter::new(
+        "db_pool_timeouts_total",
+        "Number of requests for a DB connection which timed out",
+    ))
+});
+
+fn register<T: Collector + Clone + 'static>(collector: prometheus::Result<T>) -> T {
+    let collector = collector.expect("Invalid metric");
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("Metric is registered twice");
+    collector
+}
+
+/// Records a size of a batch loaded by a `DataLoader`
+pub fn observe_dataloader_batch(loader: &str, size: usize) {
+    DATALOADER_BATCH_SIZE
+        .with_label_values(&[loader])
+        .observe(size as f64);
+}
+
+/// Records how long a client waited for a DB connection, whether it got one or not
+pub(crate) fn observe_pool_wait(wait: Duration, timed_out: bool) {
+    DB_POOL_WAIT.observe(wait.as_secs_f64());
+    if timed_out {
+        DB_POOL_TIMEOUTS.inc();
+    }
+}
+
+/// Middleware counting HTTP requests and measuring their duration
+pub async fn track_http_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started_at = Instant::now();
+    let method = req.method().to_string();
+    // a route pattern rather than a path keeps the number of label values bounded
+    let path = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.call(req).await;
+
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &path, status.as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &path])
+        .observe(started_at.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Counts GraphQL operations and measures duration of each resolver.
+/// Clients name operations as they want, so operations are counted by their root fields,
+/// which are limited by the schema; an operation with several root fields is counted for each
+pub struct GraphQLMetrics;
+
+impl ExtensionFactory for GraphQLMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLMetricsExtension {
+            root_fields: Mutex::new(BTreeSet::new()),
+        })
+    }
+}
+
+// an instance is created for each request
+struct GraphQLMetricsExtension {
+    root_fields: Mutex<BTreeSet<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for GraphQLMetricsExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let response = next.run(ctx, operation_name).await;
+        let status = if response.is_ok() { "ok" } else { "error" };
+        let root_fields = std::mem::take(
+            &mut *self
+                .root_fields
+                .lock()
+                .expect("Root fields lock is poisoned"),
+        );
+        for root_field in &root_fields {
+            GRAPHQL_OPERATIONS
+                .with_label_values(&[root_field, status])
+                .inc();
+        }
+        response
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if info.path_node.parent.is_none() {
+            self.root_fields
+                .lock()
+                .expect("Root fields lock is poisoned")
+                .insert(info.name.to_string());
+        }
+        if info.is_for_introspection {
+            return next.run(ctx, info).await;
+        }
+
+        let parent_type = info.parent_type.to_string();
+        let field = info.name.to_string();
+        let started_at = Instant::now();
+        let result = next.run(ctx, info).await;
+        GRAPHQL_RESOLVER_DURATION
+            .with_label_values(&[&parent_type, &field])
+            .observe(started_at.elapsed().as_secs_f64());
+        result
+    }
+}
+
+/// Exposes the metrics in the Prometheus text format
+pub async fn metrics(pool: web::Data<PgPool>) -> HttpResponse {
+    let stats = pool.stats();
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(stats.in_use as i64);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(stats.idle as i64);
+    DB_POOL_MAX_SIZE.set(stats.max_size as i64);
+    DB_POOL_WAITING.set(stats.waiting as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    match encoder.encode(&REGISTRY.gather(), &mut buffer) {
+        Ok(_) => HttpResponse::Ok()
+            .content_type(encoder.format_type())
+            .body(buffer),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
@@ -0,0 +1,104 @@
+use async_graphql::connection::{Connection, Edge, OpaqueCursor};
+use async_graphql::OutputType;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{ServiceError, ServiceResult};
+
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Complexity of a paginated list field: a client pays for every node it may receive
+pub fn page_complexity(first: Option<i32>, last: Option<i32>, child_complexity: usize) -> usize {
+    let page_size = first.or(last).map_or(DEFAULT_PAGE_SIZE, |size| {
+        size.clamp(0, MAX_PAGE_SIZE as i32) as usize
+    });
+    page_size * child_complexity
+}
+
+/// Opaque cursor that wraps an entity's primary key
+pub type IdCursor = OpaqueCursor<i32>;
+
+/// Keyset pagination window. By default, a key is an integer primary key,
+/// but it can also be a sort value paired with a primary key.
+///
+/// Rows are selected by key bounds instead of OFFSET,
+/// so the cost of a page doesn't depend on its position
+pub struct KeysetPage<K = i32> {
+    pub after: Option<K>,
+    pub before: Option<K>,
+    pub limit: usize,
+    /// `true` if a client paginates with `last`/`before`
+    pub backward: bool,
+}
+
+impl<K> KeysetPage<K>
+where
+    K: Serialize + DeserializeOwned + Send + Sync,
+{
+    pub fn new(
+        after: Option<OpaqueCursor<K>>,
+        before: Option<OpaqueCursor<K>>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> ServiceResult<Self> {
+        let (limit, backward) = match (first, last) {
+            (Some(_), Some(_)) => {
+                return Err(ServiceError::bad_user_input(
+                    "Passing both \"first\" and \"last\" is not supported",
+                ))
+            }
+            (Some(first), None) => (first, false),
+            (None, Some(last)) => (last, true),
+            (None, None) => (DEFAULT_PAGE_SIZE, false),
+        };
+
+        if limit > MAX_PAGE_SIZE {
+            return Err(ServiceError::bad_user_input(format!(
+                "Page size can't be greater than {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+
+        Ok(KeysetPage {
+            after: after.map(|cursor| cursor.0),
+            before: before.map(|cursor| cursor.0),
+            limit,
+            backward,
+        })
+    }
+
+    /// One extra row is fetched to find out whether there is one more page
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit as i64 + 1
+    }
+
+    /// Builds a connection from rows fetched with [`KeysetPage::fetch_limit`]
+    /// in the order of pagination
+    pub fn into_connection<E, N>(
+        self,
+        mut rows: Vec<E>,
+        get_key: impl Fn(&E) -> K,
+        to_node: impl Fn(&E) -> N,
+    ) -> Connection<OpaqueCursor<K>, N>
+    where
+        N: OutputType,
+    {
+        let has_more = rows.len() > self.limit;
+        rows.truncate(self.limit);
+
+        let mut connection = if self.backward {
+            rows.reverse();
+            Connection::new(has_more, self.before.is_some())
+        } else {
+            Connection::new(self.after.is_some(), has_more)
+        };
+
+        connection.edges.extend(
+            rows.iter()
+                .map(|row| Edge::new(OpaqueCursor(get_key(row)), to_node(row))),
+        );
+
+        connection
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use async_graphql::{Context, Guard, Result};
+
+use crate::error::ServiceError;
+use crate::FORBIDDEN_MESSAGE;
+
+/// Named permissions of a signed-in user, such as `planets:write`.
+/// Which roles grant which permissions is decided by auth-service when it issues a token
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Permissions(BTreeSet<String>);
+
+impl Permissions {
+    pub fn contains(&self, permission: &str) -> bool {
+        self.0.contains(permission)
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for Permissions {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Permissions(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Decides whether a request may use a resolver which requires a permission.
+/// A subgraph adds the policy to schema data as `Arc<dyn AuthPolicy>`
+pub trait AuthPolicy: Send + Sync {
+    fn is_allowed(&self, permissions: Option<&Permissions>, permission: &str) -> bool;
+}
+
+/// Lets a user in only if the verified identity grants the permission
+pub struct EnforcingPolicy;
+
+impl AuthPolicy for EnforcingPolicy {
+    fn is_allowed(&self, permissions: Option<&Permissions>, permission: &str) -> bool {
+        permissions.is_some_and(|permissions| permissions.contains(permission))
+    }
+}
+
+/// Lets every request in, so tests can call guarded resolvers without an identity.
+/// Available only with the `permissive-auth` feature, which services enable for tests only
+#[cfg(feature = "permissive-auth")]
+pub struct PermissivePolicy;
+
+#[cfg(feature = "permissive-auth")]
+impl AuthPolicy for PermissivePolicy {
+    fn is_allowed(&self, _permissions: Option<&Permissions>, _permission: &str) -> bool {
+        true
+    }
+}
+
+/// Lets a user in only if they have a permission; resolvers of every subgraph can use it
+/// once `Permissions` of the verified identity are added to the request data.
+/// The [`AuthPolicy`] from schema data makes the decision, [`EnforcingPolicy`] if there is none
+pub struct PermissionGuard {
+    permission: &'static str,
+}
+
+impl PermissionGuard {
+    pub fn new(permission: &'static str) -> Self {
+        Self { permission }
+    }
+}
+
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let permissions = ctx.data_opt::<Permissions>();
+        let is_allowed = match ctx.data_opt::<Arc<dyn AuthPolicy>>() {
+            Some(policy) => policy.is_allowed(permissions, self.permission),
+            None => EnforcingPolicy.is_allowed(permissions, self.permission),
+        };
+        if is_allowed {
+            Ok(())
+        } else {
+            Err(ServiceError::forbidden(FORBIDDEN_MESSAGE).into())
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest, NextValidation,
+};
+use async_graphql::parser::parse_query;
+use async_graphql::{from_value, Request, ServerError, ServerResult, ValidationResult};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use lru::LruCache;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::db::PgPool;
+use crate::error::{ErrorCode, ServiceError, ServiceResult};
+use crate::query_limits::is_introspection;
+
+const DEFAULT_CACHE_SIZE: usize = 1000;
+const DEFAULT_MAX_QUERY_LENGTH: usize = 10_000;
+const DEFAULT_MAX_STORED_QUERIES: i64 = 10_000;
+
+// created by a migration of each service
+diesel::table! {
+    persisted_queries (hash) {
+        hash -> Varchar,
+        query -> Text,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PersistedQueriesMode {
+    /// Requests which contain a hash are rejected
+    Disabled,
+    /// A query is registered by the first request which sends it along with its hash
+    Automatic,
+    /// Only queries of the manifest or the `persisted_queries` table and introspection
+    /// are executed, and requests can't register queries
+    AllowList,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PersistedQueriesStorage {
+    /// Queries are lost on restart and aren't shared between instances
+    Memory,
+    /// The `persisted_queries` table backs the in-memory cache
+    Postgres,
+}
+
+pub struct PersistedQueriesConfig {
+    pub mode: PersistedQueriesMode,
+    pub storage: PersistedQueriesStorage,
+    pub cache_size: NonZeroUsize,
+    /// Longer queries are executed, but not registered
+    pub max_query_length: usize,
+    /// Once the `persisted_queries` table has this many queries, new ones are only cached in memory
+    pub max_stored_queries: i64,
+    /// Queries of the manifest by their hashes
+    pub manifest: HashMap<String, String>,
+}
+
+impl PersistedQueriesConfig {
+    /// Reads `PERSISTED_QUERIES_MODE` (`disabled`, `automatic` by default or `allow_list`),
+    /// `PERSISTED_QUERIES_STORAGE` (`memory` by default or `postgres`),
+    /// `PERSISTED_QUERIES_CACHE_SIZE` (the number of queries kept in memory),
+    /// `PERSISTED_QUERIES_MAX_QUERY_LENGTH` (in bytes), `PERSISTED_QUERIES_MAX_STORED`
+    /// (the number of queries kept in Postgres) and `PERSISTED_QUERIES_MANIFEST`
+    /// (a path to an Apollo persisted query manifest)
+    pub fn from_env() -> Result<Self, String> {
+        let mode = match env::var("PERSISTED_QUERIES_MODE")
+            .unwrap_or_else(|_| "automatic".to_string())
+            .as_str()
+        {
+            "disabled" => PersistedQueriesMode::Disabled,
+            "automatic" => PersistedQueriesMode::Automatic,
+            "allow_list" => PersistedQueriesMode::AllowList,
+            other => return Err(format!("Unknown persisted queries mode: {}", other)),
+        };
+        let storage = match env::var("PERSISTED_QUERIES_STORAGE")
+            .unwrap_or_else(|_| "memory".to_string())
+            .as_str()
+        {
+            "memory" => PersistedQueriesStorage::Memory,
+            "postgres" => PersistedQueriesStorage::Postgres,
+            other => return Err(format!("Unknown persisted queries storage: {}", other)),
+        };
+        let cache_size = match env::var("PERSISTED_QUERIES_CACHE_SIZE") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("Invalid value of PERSISTED_QUERIES_CACHE_SIZE: {}", e))?,
+            Err(_) => NonZeroUsize::new(DEFAULT_CACHE_SIZE).expect("Cache size is zero"),
+        };
+        let max_query_length = match env::var("PERSISTED_QUERIES_MAX_QUERY_LENGTH") {
+            Ok(value) => value.parse().map_err(|e| {
+                format!("Invalid value of PERSISTED_QUERIES_MAX_QUERY_LENGTH: {}", e)
+            })?,
+            Err(_) => DEFAULT_MAX_QUERY_LENGTH,
+        };
+        let max_stored_queries = match env::var("PERSISTED_QUERIES_MAX_STORED") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("Invalid value of PERSISTED_QUERIES_MAX_STORED: {}", e))?,
+            Err(_) => DEFAULT_MAX_STORED_QUERIES,
+        };
+        let manifest = match env::var("PERSISTED_QUERIES_MANIFEST") {
+            Ok(path) => read_manifest(&path)?,
+            Err(_) => HashMap::new(),
+        };
+
+        // the allow-list would be empty, since requests can't register queries in that mode
+        if mode == PersistedQueriesMode::AllowList
+            && storage == PersistedQueriesStorage::Memory
+            && manifest.is_empty()
+        {
+            return Err(
+                "Allow-list of persisted queries requires a manifest or the postgres storage"
+                    .to_string(),
+            );
+        }
+
+        Ok(PersistedQueriesConfig {
+            mode,
+            storage,
+            cache_size,
+            max_query_length,
+            max_stored_queries,
+            manifest,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    format: String,
+    version: i32,
+    operations: Vec<ManifestOperation>,
+}
+
+#[derive(Deserialize)]
+struct ManifestOperation {
+    id: String,
+    body: String,
+}
+
+/// Reads a manifest generated by `@apollo/generate-persisted-query-manifest`
+/// with the default SHA-256 ids
+fn read_manifest(path: &str) -> Result<HashMap<String, String>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Can't read persisted query manifest {}: {}", path, e))?;
+    let manifest: Manifest = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid persisted query manifest {}: {}", path, e))?;
+    if manifest.format != "apollo-persisted-query-manifest" || manifest.version != 1 {
+        return Err(format!(
+            "Unsupported persisted query manifest {}: {} version {}",
+            path, manifest.format, manifest.version
+        ));
+    }
+
+    manifest
+        .operations
+        .into_iter()
+        .map(|operation| {
+            if query_hash(&operation.body) == operation.id {
+                Ok((operation.id, operation.body))
+            } else {
+                Err(format!(
+                    "Id of operation {} in persisted query manifest {} isn't a hash of its body",
+                    operation.id, path
+                ))
+            }
+        })
+        .collect()
+}
+
+/// SHA-256 hash of a query in the hex format, as clients compute it
+pub fn query_hash(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+/// Support of Apollo automatic persisted queries: a client sends a hash of a query
+/// instead of its text and sends the text only if the hash is unknown.
+///
+/// Unlike `async_graphql::extensions::ApolloPersistedQueries`, the text of a query
+/// is stored, so it can be kept in Postgres, and the query is parsed as usual,
+/// so other extensions see the parsing.
+///
+/// A query is registered only after it passes validation, so the extension should be added
+/// before `QueryLimits`: then the limits are checked within its validation
+pub struct PersistedQueries {
+    mode: PersistedQueriesMode,
+    max_query_length: usize,
+    store: QueryStore,
+}
+
+impl PersistedQueries {
+    pub fn new(config: &PersistedQueriesConfig, pool: PgPool) -> Self {
+        let pool = match config.storage {
+            PersistedQueriesStorage::Memory => None,
+            PersistedQueriesStorage::Postgres => Some(pool),
+        };
+        PersistedQueries {
+            mode: config.mode,
+            max_query_length: config.max_query_length,
+            store: QueryStore {
+                manifest: Arc::new(config.manifest.clone()),
+                cache: Arc::new(Mutex::new(LruCache::new(config.cache_size))),
+                pool,
+                max_stored_queries: config.max_stored_queries,
+            },
+        }
+    }
+}
+
+impl ExtensionFactory for PersistedQueries {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PersistedQueriesExtension {
+            mode: self.mode,
+            max_query_length: self.max_query_length,
+            store: self.store.clone(),
+            unregistered_query: Mutex::new(None),
+        })
+    }
+}
+
+/// Queries by their hashes; queries of the manifest are always in memory
+/// and recently used ones of the others are cached
+#[derive(Clone)]
+struct QueryStore {
+    manifest: Arc<HashMap<String, String>>,
+    cache: Arc<Mutex<LruCache<String, String>>>,
+    pool: Option<PgPool>,
+    max_stored_queries: i64,
+}
+
+impl QueryStore {
+    async fn get(&self, hash: &str) -> ServiceResult<Option<String>> {
+        if let Some(query) = self.manifest.get(hash) {
+            return Ok(Some(query.clone()));
+        }
+        if let Some(query) = self.cache().get(hash) {
+            return Ok(Some(query.clone()));
+        }
+        let Some(pool) = &self.pool else {
+            return Ok(None);
+        };
+
+        let mut conn = pool.get().await?;
+        let query = persisted_queries::table
+            .find(hash)
+            .select(persisted_queries::query)
+            .first::<String>(&mut *conn)
+            .await
+            .optional()?;
+        if let Some(query) = &query {
+            self.cache().put(hash.to_string(), query.clone());
+        }
+        Ok(query)
+    }
+
+    /// Queries aren't deleted from Postgres, so they stop being stored there once the limit
+    /// is reached and keep being registered only in memory
+    async fn register(&self, hash: String, query: String) -> ServiceResult<()> {
+        if let Some(pool) = &self.pool {
+            let mut conn = pool.get().await?;
+            let stored_queries: i64 = persisted_queries::table
+                .count()
+                .get_result(&mut *conn)
+                .await?;
+            if stored_queries < self.max_stored_queries {
+                diesel::insert_into(persisted_queries::table)
+                    .values((
+                        persisted_queries::hash.eq(&hash),
+                        persisted_queries::query.eq(&query),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+        self.cache().put(hash, query);
+        Ok(())
+    }
+
+    fn cache(&self) -> MutexGuard<'_, LruCache<String, String>> {
+        self.cache
+            .lock()
+            .expect("Persisted queries cache is poisoned")
+    }
+}
+
+#[derive(Deserialize)]
+struct PersistedQuery {
+    version: i32,
+    #[serde(rename = "sha256Hash")]
+    sha256_hash: String,
+}
+
+// an instance is created for each request
+struct PersistedQueriesExtension {
+    mode: PersistedQueriesMode,
+    max_query_length: usize,
+    store: QueryStore,
+    /// Hash and text of a new query, which is registered once it's valid
+    unregistered_query: Mutex<Option<(String, String)>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for PersistedQueriesExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let request = self
+            .resolve_query(request)
+            .await
+            .map_err(ServerError::from)?;
+        next.run(ctx, request).await
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        let unregistered_query = self
+            .unregistered_query
+            .lock()
+            .expect("Unregistered query lock is poisoned")
+            .take();
+        if let Some((hash, query)) = unregistered_query {
+            self.store
+                .register(hash, query)
+                .await
+                .map_err(|e| vec![ServerError::from(e)])?;
+        }
+        Ok(result)
+    }
+}
+
+impl PersistedQueriesExtension {
+    /// Fills in the text of a query sent as a hash and remembers a new query to register it
+    async fn resolve_query(&self, mut request: Request) -> ServiceResult<Request> {
+        let Some(value) = request.extensions.remove("persistedQuery") else {
+            if self.mode == PersistedQueriesMode::AllowList {
+                self.check_allowed(&request.query, &query_hash(&request.query))
+                    .await?;
+            }
+            return Ok(request);
+        };
+
+        if self.mode == PersistedQueriesMode::Disabled {
+            return Err(ServiceError::new(
+                ErrorCode::PersistedQueryNotSupported,
+                "PersistedQueryNotSupported",
+            ));
+        }
+        let persisted_query: PersistedQuery = from_value(value)
+            .map_err(|_| ServiceError::bad_user_input("Invalid persistedQuery extension"))?;
+        if persisted_query.version != 1 {
+            return Err(ServiceError::bad_user_input(format!(
+                "Version {} of persisted queries isn't supported",
+                persisted_query.version
+            )));
+        }
+
+        if request.query.is_empty() {
+            // clients expect exactly this message to send the query again along with its text
+            request.query = self
+                .store
+                .get(&persisted_query.sha256_hash)
+                .await?
+                .ok_or_else(|| {
+                    ServiceError::new(ErrorCode::PersistedQueryNotFound, "PersistedQueryNotFound")
+                })?;
+            return Ok(request);
+        }
+
+        let hash = query_hash(&request.query);
+        if hash != persisted_query.sha256_hash {
+            return Err(ServiceError::bad_user_input(
+                "Hash of the persisted query doesn't match its text",
+            ));
+        }
+        match self.mode {
+            PersistedQueriesMode::AllowList => self.check_allowed(&request.query, &hash).await?,
+            _ if request.query.len() <= self.max_query_length => {
+                *self
+                    .unregistered_query
+                    .lock()
+                    .expect("Unregistered query lock is poisoned") =
+                    Some((hash, request.query.clone()));
+            }
+            _ => {}
+        }
+        Ok(request)
+    }
+
+    // introspection is allowed, so the router can compose the supergraph
+    async fn check_allowed(&self, query: &str, hash: &str) -> ServiceResult<()> {
+        let allowed = self.store.get(hash).await?.is_some()
+            || parse_query(query).is_ok_and(|document| is_introspection(&document));
+        if allowed {
+            Ok(())
+        } else {
+            Err(ServiceError::new(
+                ErrorCode::PersistedQueryNotInList,
+                "Operation isn't in the allow-list of persisted queries",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::{query_hash, read_manifest};
+
+    const QUERY: &str = "{ planets { name } }";
+
+    fn write_manifest(name: &str, id: &str) -> String {
+        let manifest = serde_json::json!({
+            "format": "apollo-persisted-query-manifest",
+            "version": 1,
+            "operations": [{ "id": id, "name": "Planets", "type": "query", "body": QUERY }]
+        });
+        let path = env::temp_dir().join(name);
+        fs::write(&path, manifest.to_string()).expect("Can't write manifest");
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn manifest_is_read() {
+        let hash = query_hash(QUERY);
+        let path = write_manifest("persisted-query-manifest.json", &hash);
+        let manifest = read_manifest(&path).expect("Can't read manifest");
+        assert_eq!(Some(QUERY), manifest.get(&hash).map(String::as_str));
+    }
+
+    #[test]
+    fn manifest_with_wrong_id_is_rejected() {
+        let path = write_manifest("wrong-persisted-query-manifest.json", "wrong");
+        assert!(read_manifest(&path).is_err());
+    }
+}
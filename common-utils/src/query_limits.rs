@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextValidation,
+};
+use async_graphql::parser::types::{ExecutableDocument, Selection, SelectionSet};
+use async_graphql::{ServerError, ServerResult, ValidationResult, Variables};
+
+use crate::error::ServiceError;
+
+const DEFAULT_MAX_DEPTH: usize = 10;
+const DEFAULT_MAX_COMPLEXITY: usize = 1000;
+
+/// Rejects too deep or too complex operations.
+///
+/// Unlike `SchemaBuilder::limit_depth` and `limit_complexity`, the limits aren't applied
+/// to introspection, so tools like the router and playground keep working. Complexity
+/// of a field is 1 plus complexity of its selection unless the field declares its own cost
+#[derive(Copy, Clone)]
+pub struct QueryLimits {
+    pub max_depth: usize,
+    pub max_complexity: usize,
+}
+
+impl QueryLimits {
+    /// Reads `GRAPHQL_MAX_DEPTH` and `GRAPHQL_MAX_COMPLEXITY`
+    pub fn from_env() -> Result<Self, String> {
+        Ok(QueryLimits {
+            max_depth: parse_limit("GRAPHQL_MAX_DEPTH", DEFAULT_MAX_DEPTH)?,
+            max_complexity: parse_limit("GRAPHQL_MAX_COMPLEXITY", DEFAULT_MAX_COMPLEXITY)?,
+        })
+    }
+}
+
+fn parse_limit(name: &str, default: usize) -> Result<usize, String> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| format!("Invalid value of {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+impl ExtensionFactory for QueryLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(QueryLimitsExtension {
+            limits: *self,
+            introspection: AtomicBool::new(false),
+        })
+    }
+}
+
+// an instance is created for each request
+struct QueryLimitsExtension {
+    limits: QueryLimits,
+    introspection: AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl Extension for QueryLimitsExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        self.introspection
+            .store(is_introspection(&document), Ordering::Relaxed);
+        Ok(document)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        if self.introspection.load(Ordering::Relaxed) {
+            return Ok(result);
+        }
+
+        if result.depth > self.limits.max_depth {
+            return Err(limit_exceeded(format!(
+                "Query is nested too deep: {} levels while the limit is {}",
+                result.depth, self.limits.max_depth
+            )));
+        }
+        if result.complexity > self.limits.max_complexity {
+            return Err(limit_exceeded(format!(
+                "Query is too complex: complexity is {} while the limit is {}",
+                result.complexity, self.limits.max_complexity
+            )));
+        }
+
+        Ok(result)
+    }
+}
+
+fn limit_exceeded(message: String) -> Vec<ServerError> {
+    vec![ServiceError::bad_user_input(message).into()]
+}
+
+/// Whether all operations of the document select only introspection fields,
+/// like `__schema` or `__type`, at the top level
+pub(crate) fn is_introspection(document: &ExecutableDocument) -> bool {
+    let mut visited_fragments = HashSet::new();
+    document.operations.iter().all(|(_, operation)| {
+        selects_only_introspection(
+            document,
+            &operation.node.selection_set.node,
+            &mut visited_fragments,
+        )
+    })
+}
+
+fn selects_only_introspection<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    visited_fragments: &mut HashSet<&'a str>,
+) -> bool {
+    selection_set
+        .items
+        .iter()
+        .all(|selection| match &selection.node {
+            Selection::Field(field) => is_introspection_field(&field.node.name.node),
+            Selection::FragmentSpread(spread) => {
+                let name = spread.node.fragment_name.node.as_str();
+                // the document isn't validated yet, so fragments may form a cycle
+                if !visited_fragments.insert(name) {
+                    return true;
+                }
+                document.fragments.get(name).is_some_and(|fragment| {
+                    selects_only_introspection(
+                        document,
+                        &fragment.node.selection_set.node,
+                        visited_fragments,
+                    )
+                })
+            }
+            Selection::InlineFragment(fragment) => selects_only_introspection(
+                document,
+                &fragment.node.selection_set.node,
+                visited_fragments,
+            ),
+        })
+}
+
+// the router and rover get SDL of a subgraph with `_service`
+fn is_introspection_field(name: &str) -> bool {
+    name.starts_with("__") || name == "_service"
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::HttpMessage;
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextRequest};
+use async_graphql::Response;
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// IDs longer than that are replaced, so a client can't flood logs through the header
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// ID of an HTTP request which a client, the router or this service assigned to it
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+/// Middleware which takes a request ID from the `x-request-id` header or generates one.
+/// The ID is added to all logs of the request and returned in the response header
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LENGTH)
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let span = tracing::info_span!(
+        "http_request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+    let mut response = next.call(req).instrument(span).await?;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
+
+/// Adds the ID of the current request to extensions of GraphQL errors,
+/// so a client can report it. The ID is expected in request data
+pub struct RequestIdInErrors;
+
+impl ExtensionFactory for RequestIdInErrors {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RequestIdInErrorsExtension)
+    }
+}
+
+struct RequestIdInErrorsExtension;
+
+#[async_trait::async_trait]
+impl Extension for RequestIdInErrorsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut response = next.run(ctx).await;
+        if let Some(RequestId(request_id)) = ctx.data_opt::<RequestId>() {
+            for error in &mut response.errors {
+                error
+                    .extensions
+                    .get_or_insert_with(Default::default)
+                    .set("requestId", request_id.as_str());
+            }
+        }
+        response
+    }
+}
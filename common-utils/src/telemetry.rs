@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+use actix_web::http::header::HeaderMap;
+use actix_web::HttpRequest;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextResolve, ResolveInfo,
+};
+use async_graphql::{Response, ServerResult, Value};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Config, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub enum TraceExporter {
+    /// Spans are only used to propagate trace context
+    None,
+    Stdout,
+    Otlp {
+        endpoint: String,
+    },
+}
+
+impl TraceExporter {
+    /// `OTEL_TRACES_EXPORTER` is `none` (default), `stdout` or `otlp`; the latter sends spans
+    /// to `OTEL_EXPORTER_OTLP_ENDPOINT` (`http://localhost:4317` by default)
+    pub fn from_env() -> Result<Self, String> {
+        let exporter = env::var("OTEL_TRACES_EXPORTER").unwrap_or_else(|_| "none".to_string());
+
+        match exporter.as_str() {
+            "none" => Ok(TraceExporter::None),
+            "stdout" => Ok(TraceExporter::Stdout),
+            "otlp" => Ok(TraceExporter::Otlp {
+                endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                    .unwrap_or_else(|_| "http://localhost:4317".to_string()),
+            }),
+            other => Err(format!("Unknown traces exporter: {}", other)),
+        }
+    }
+}
+
+/// Installs a global subscriber which writes events as JSON and exports spans;
+/// trace context is propagated in the W3C format. Log levels are set per module
+/// in `RUST_LOG`, e.g. `info,planets_service::outbox=debug`; the default level is `info`
+pub fn init_tracing(service_name: &'static str, exporter: TraceExporter) -> Result<(), BoxError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let config = Config::default()
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]));
+    let provider = match exporter {
+        TraceExporter::None => None,
+        TraceExporter::Stdout => Some(
+            TracerProvider::builder()
+                .with_simple_exporter(opentelemetry_stdout::SpanExporter::default())
+                .with_config(config)
+                .build(),
+        ),
+        TraceExporter::Otlp { endpoint } => Some(
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(config)
+                .install_batch(runtime::Tokio)?,
+        ),
+    };
+    let opentelemetry_layer = provider.map(|provider| {
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name));
+        global::set_tracer_provider(provider);
+        layer
+    });
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer().json().flatten_event(true))
+        .with(opentelemetry_layer)
+        .try_init()?;
+
+    Ok(())
+}
+
+/// Exports spans which are still buffered
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+/// Span of a GraphQL request that continues a trace started by a client or the router
+pub fn request_span(req: &HttpRequest) -> Span {
+    let span = tracing::info_span!("graphql_request");
+    span.set_parent(global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    }));
+    span
+}
+
+/// Creates spans for execution of a GraphQL operation and each of its resolvers
+pub struct GraphQLTracing;
+
+impl ExtensionFactory for GraphQLTracing {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLTracingExtension)
+    }
+}
+
+struct GraphQLTracingExtension;
+
+#[async_trait::async_trait]
+impl Extension for GraphQLTracingExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let span = tracing::info_span!(
+            "graphql_execute",
+            operation_name = operation_name.unwrap_or("anonymous")
+        );
+        next.run(ctx, operation_name).instrument(span).await
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if info.is_for_introspection {
+            return next.run(ctx, info).await;
+        }
+
+        let span = tracing::info_span!(
+            "graphql_resolve",
+            path = %info.path_node,
+            parent_type = info.parent_type,
+            return_type = info.return_type,
+        );
+        next.run(ctx, info).instrument(span).await
+    }
+}
+
+/// Trace context of the current span as key-value pairs, e.g. to put it in message headers
+pub fn current_trace_context() -> HashMap<String, String> {
+    let context = Span::current().context();
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut carrier));
+    carrier
+}
+
+pub fn trace_context_from(carrier: &HashMap<String, String>) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(carrier))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
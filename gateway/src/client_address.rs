@@ -0,0 +1,116 @@
+//! Replaces `X-Forwarded-For` of a client with the address of the client, which subgraphs
+//! limit sign-in attempts by. A client can put anything into the header, so only the entry
+//! appended by a trusted proxy in front of the router is forwarded
+use std::net::{IpAddr, SocketAddr};
+use std::ops::ControlFlow;
+
+use apollo_router::{
+    layers::ServiceBuilderExt,
+    plugin::{Plugin, PluginInit},
+    register_plugin,
+    services::supergraph,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::{BoxError, ServiceBuilder, ServiceExt};
+use tracing::warn;
+
+const CLIENT_ADDRESS_CONTEXT_PARAM_NAME: &str = "client_address";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(Deserialize, JsonSchema)]
+struct ClientAddressConfig {
+    /// Number of proxies in front of the router, each of which appends the address of its peer
+    /// to `X-Forwarded-For`. The router doesn't see the address of its own peer, so if it's
+    /// exposed directly (`0`), no address is forwarded and subgraphs don't limit by IP
+    #[serde(default)]
+    trusted_proxy_hops: usize,
+}
+
+struct ClientAddress {
+    trusted_proxy_hops: usize,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ClientAddress {
+    type Config = ClientAddressConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        if init.config.trusted_proxy_hops == 0 {
+            warn!(
+                "No trusted proxies are configured, so client addresses aren't forwarded \
+                and subgraphs don't limit sign-in attempts per IP"
+            );
+        }
+        Ok(ClientAddress {
+            trusted_proxy_hops: init.config.trusted_proxy_hops,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let trusted_proxy_hops = self.trusted_proxy_hops;
+
+        let handler = move |request: supergraph::Request| {
+            let forwarded_for = request
+                .supergraph_request
+                .headers()
+                .get_all(X_FORWARDED_FOR)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(",");
+            if let Some(client_address) = client_address(&forwarded_for, trusted_proxy_hops) {
+                if let Err(error) = request.context.insert(
+                    CLIENT_ADDRESS_CONTEXT_PARAM_NAME,
+                    client_address.to_string(),
+                ) {
+                    warn!(%error, "Failed to pass a client's address");
+                }
+            }
+            Ok::<_, BoxError>(ControlFlow::Continue(request))
+        };
+
+        ServiceBuilder::new()
+            .checkpoint(handler)
+            .service(service)
+            .boxed()
+    }
+}
+
+/// Entries before the one appended by the outermost trusted proxy are set by the client
+fn client_address(forwarded_for: &str, trusted_proxy_hops: usize) -> Option<IpAddr> {
+    let entries = forwarded_for
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect::<Vec<_>>();
+    let index = entries.len().checked_sub(trusted_proxy_hops)?;
+    let entry = entries.get(index).filter(|_| trusted_proxy_hops > 0)?;
+    entry
+        .parse::<IpAddr>()
+        .or_else(|_| entry.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+}
+
+register_plugin!("demo", "client_address", ClientAddress);
+
+#[cfg(test)]
+mod tests {
+    use super::client_address;
+
+    #[test]
+    fn spoofed_entries_are_ignored() {
+        let forwarded_for = "1.1.1.1, 2.2.2.2, 203.0.113.7";
+        assert_eq!(
+            Some("203.0.113.7".parse().unwrap()),
+            client_address(forwarded_for, 1)
+        );
+        assert_eq!(
+            Some("2.2.2.2".parse().unwrap()),
+            client_address(forwarded_for, 2)
+        );
+        assert_eq!(None, client_address(forwarded_for, 0));
+        assert_eq!(None, client_address("203.0.113.7", 2));
+        assert_eq!(None, client_address("", 1));
+    }
+}
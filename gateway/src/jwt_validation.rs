@@ -1,6 +1,12 @@
 //! DISCLAIMER:
 //! This is an example for only illustrative purposes. It demonstrates how to perform JWT verification via a router plugin
+use std::collections::HashSet;
+use std::future::Future;
 use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
 
 use apollo_router::{
     graphql,
@@ -12,24 +18,56 @@ use apollo_router::{
 };
 use http::header::AUTHORIZATION;
 use http::StatusCode;
-use jsonwebtoken::{decode, DecodingKey, TokenData, Validation};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{
+    decode, decode_header, get_current_timestamp, Algorithm, DecodingKey, TokenData, Validation,
+};
 use schemars::JsonSchema;
 use serde::Deserialize;
 use tower::{BoxError, ServiceBuilder, ServiceExt};
-use tracing::debug;
+use tracing::{debug, warn};
+
+use common_utils::identity::{IdentityClaims, IdentityKey, IDENTITY_TOKEN_LIFETIME_SECS};
+use common_utils::{Claims, RevokedTokens};
 
-use common_utils::Claims;
+const IDENTITY_CONTEXT_PARAM_NAME: &str = "internal_identity";
 
-const ROLE_CONTEXT_PARAM_NAME: &str = "user_role";
+/// Where public keys of auth-service are loaded from; the router doesn't hold a signing secret
+#[derive(Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum JwksSource {
+    File(PathBuf),
+    /// `/.well-known/jwks.json` endpoint of auth-service
+    Url(String),
+}
 
 #[derive(Deserialize, JsonSchema)]
 struct JwtValidationConfig {
-    secret_key: String,
+    jwks: JwksSource,
+    /// Keys are reloaded periodically, so a key added on rotation is picked up
+    #[serde(default = "default_jwks_reload_interval_secs")]
+    jwks_reload_interval_secs: u64,
+    /// Endpoint of auth-service that lists IDs of revoked tokens; if it isn't set,
+    /// tokens are valid until they expire
+    revoked_tokens_url: Option<String>,
+    #[serde(default = "default_revoked_tokens_poll_interval_secs")]
+    revoked_tokens_poll_interval_secs: u64,
+    /// Secret shared with subgraphs which identities of users are signed with
+    identity_secret: String,
+}
+
+fn default_jwks_reload_interval_secs() -> u64 {
+    60
+}
+
+fn default_revoked_tokens_poll_interval_secs() -> u64 {
+    10
 }
 
-#[derive(Debug)]
 struct JwtValidation {
-    secret_key: String,
+    jwks: Arc<RwLock<JwkSet>>,
+    revoked_tokens: Arc<RwLock<HashSet<String>>>,
+    identity_key: IdentityKey,
 }
 
 #[async_trait::async_trait]
@@ -37,13 +75,50 @@ impl Plugin for JwtValidation {
     type Config = JwtValidationConfig;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let config = init.config;
+        let identity_key = IdentityKey::new(config.identity_secret.as_bytes())?;
+        let client = reqwest::Client::new();
+
+        let jwks = match load_jwks(client.clone(), config.jwks.clone()).await {
+            Ok(jwks) => jwks,
+            // auth-service may start later than the router; until then, all tokens are rejected
+            Err(e) if matches!(config.jwks, JwksSource::Url(_)) => {
+                warn!(error = %e, "Can't load JWKS");
+                JwkSet { keys: Vec::new() }
+            }
+            Err(e) => return Err(e),
+        };
+        let jwks = Arc::new(RwLock::new(jwks));
+        let jwks_source = config.jwks;
+        let jwks_client = client.clone();
+        tokio::spawn(poll(
+            "JWKS",
+            Duration::from_secs(config.jwks_reload_interval_secs),
+            Arc::downgrade(&jwks),
+            move || load_jwks(jwks_client.clone(), jwks_source.clone()),
+        ));
+
+        let revoked_tokens = Arc::new(RwLock::new(HashSet::new()));
+        if let Some(url) = config.revoked_tokens_url {
+            tokio::spawn(poll(
+                "revoked tokens",
+                Duration::from_secs(config.revoked_tokens_poll_interval_secs),
+                Arc::downgrade(&revoked_tokens),
+                move || fetch_revoked_tokens(client.clone(), url.clone()),
+            ));
+        }
+
         Ok(JwtValidation {
-            secret_key: init.config.secret_key,
+            jwks,
+            revoked_tokens,
+            identity_key,
         })
     }
 
     fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
-        let jwt_secret_key = self.secret_key.clone();
+        let jwks = Arc::clone(&self.jwks);
+        let revoked_tokens = Arc::clone(&self.revoked_tokens);
+        let identity_key = self.identity_key.clone();
 
         fn failure_message(
             context: Context,
@@ -71,7 +146,7 @@ impl Plugin for JwtValidation {
                 .get(AUTHORIZATION.as_str())
             {
                 Some(auth_header_value) => auth_header_value.to_str(),
-                // in this project, I decided to allow the passage of requests without the AUTHORIZATION header. then each subgraph performs authorization based on the ROLE header
+                // in this project, I decided to allow the passage of requests without the AUTHORIZATION header. then each subgraph performs authorization based on the signed identity header
                 // your case may be different. be careful
                 None => return Ok(ControlFlow::Continue(request)),
             };
@@ -112,14 +187,49 @@ impl Plugin for JwtValidation {
 
             let jwt = auth_header_value_parts[1].trim_end();
 
-            match decode_jwt(&jwt, &jwt_secret_key) {
+            let decoding_result = decode_jwt(jwt, &jwks.read().expect("JWKS lock is poisoned"));
+            match decoding_result {
                 Ok(token_data) => {
-                    let role = token_data.claims.role;
-                    debug!("User role is: {}", &role);
-                    if let Err(error) = request.context.insert(ROLE_CONTEXT_PARAM_NAME, role) {
+                    let is_revoked = revoked_tokens
+                        .read()
+                        .expect("Revoked tokens lock is poisoned")
+                        .contains(&token_data.claims.jti);
+                    if is_revoked {
+                        return failure_message(
+                            request.context,
+                            "JWT is revoked".to_string(),
+                            StatusCode::UNAUTHORIZED,
+                        );
+                    }
+
+                    let claims = token_data.claims;
+                    debug!("User role is: {}", &claims.role);
+                    // subgraphs trust only a signed identity, which is short-lived,
+                    // so a leaked header can't be replayed for long
+                    let identity_claims = IdentityClaims {
+                        exp: (claims.exp.max(0) as u64)
+                            .min(get_current_timestamp() + IDENTITY_TOKEN_LIFETIME_SECS),
+                        sub: claims.sub,
+                        role: claims.role,
+                        permissions: claims.permissions,
+                    };
+                    let identity = match identity_key.sign(&identity_claims) {
+                        Ok(identity) => identity,
+                        Err(error) => {
+                            return failure_message(
+                                request.context,
+                                format!("Failed to sign a user's identity: {}", error),
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                            )
+                        }
+                    };
+                    if let Err(error) = request
+                        .context
+                        .insert(IDENTITY_CONTEXT_PARAM_NAME, identity)
+                    {
                         return failure_message(
                             request.context,
-                            format!("Failed to pass a user's role: {}", error),
+                            format!("Failed to pass a user's identity: {}", error),
                             StatusCode::INTERNAL_SERVER_ERROR,
                         );
                     }
@@ -144,13 +254,71 @@ impl Plugin for JwtValidation {
     }
 }
 
-// this also includes JWT validation
-fn decode_jwt(jwt: &str, secret_key: &str) -> jsonwebtoken::errors::Result<TokenData<Claims>> {
-    decode::<Claims>(
+// this also includes JWT validation. The algorithm is taken from the key, not from the token,
+// so a token can't choose a weaker one
+fn decode_jwt(jwt: &str, jwks: &JwkSet) -> Result<TokenData<Claims>, BoxError> {
+    let key_id = decode_header(jwt)?.kid.ok_or("JWT doesn't have a key ID")?;
+    let jwk = jwks
+        .find(&key_id)
+        .ok_or_else(|| format!("Unknown key ID: {}", key_id))?;
+    let key_algorithm = jwk
+        .common
+        .key_algorithm
+        .ok_or("Key doesn't have an algorithm")?;
+    let algorithm = Algorithm::from_str(&key_algorithm.to_string())?;
+    Ok(decode::<Claims>(
         jwt,
-        &DecodingKey::from_secret(secret_key.as_bytes()),
-        &Validation::default(),
-    )
+        &DecodingKey::from_jwk(jwk)?,
+        &Validation::new(algorithm),
+    )?)
+}
+
+async fn load_jwks(client: reqwest::Client, source: JwksSource) -> Result<JwkSet, BoxError> {
+    match source {
+        JwksSource::File(path) => Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?),
+        JwksSource::Url(url) => Ok(client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?),
+    }
+}
+
+async fn fetch_revoked_tokens(
+    client: reqwest::Client,
+    url: String,
+) -> Result<HashSet<String>, BoxError> {
+    let revoked_tokens: RevokedTokens = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(revoked_tokens.jtis.into_iter().collect())
+}
+
+/// Reloads a value periodically; the task stops when the plugin is dropped,
+/// for example, on reload of the configuration
+async fn poll<T, F, Fut>(name: &'static str, interval: Duration, target: Weak<RwLock<T>>, load: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, BoxError>>,
+{
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let Some(target) = target.upgrade() else {
+            return;
+        };
+        match load().await {
+            Ok(value) => *target.write().expect("Lock is poisoned") = value,
+            // the previous value is kept, so, for example, revoked tokens stay rejected
+            Err(e) => warn!(error = %e, "Can't load {}", name),
+        }
+    }
 }
 
 register_plugin!("demo", "jwt_validation", JwtValidation);
@@ -163,7 +331,10 @@ mod tests {
         let config = serde_json::json!({
             "plugins": {
                 "demo.jwt_validation": {
-                    "secret_key": "example"
+                    "jwks": {
+                        "url": "http://localhost:8003/.well-known/jwks.json"
+                    },
+                    "identity_secret": "test secret which is long enough for HS256"
                 }
             }
         });
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use dotenv::dotenv;
 
+mod client_address;
 mod jwt_validation;
 
 fn main() -> Result<()> {
@@ -0,0 +1,12 @@
+create table outbox (
+    id serial primary key,
+    message_key varchar not null,
+    payload text not null,
+    created_at timestamp not null default now(),
+    attempts integer not null default 0,
+    last_error varchar,
+    next_attempt_at timestamp not null default now(),
+    delivered_at timestamp
+);
+
+create index outbox_pending_idx on outbox (next_attempt_at) where delivered_at is null;
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{self, Sender};
+
+use common_utils::telemetry;
+
+use crate::event_bus::{BoxError, EventBus, ReceivedMessage};
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Delivers messages within the process. Intended for tests and local development
+pub struct InMemoryEventBus {
+    sender: Sender<ReceivedMessage>,
+}
+
+impl Default for InMemoryEventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        InMemoryEventBus { sender }
+    }
+}
+
+#[async_trait]
+impl EventBus for InMemoryEventBus {
+    async fn publish(&self, _key: &str, payload: &[u8]) -> Result<(), BoxError> {
+        // an error only means that there are no subscribers at the moment
+        let _ = self.sender.send(ReceivedMessage {
+            payload: payload.to_vec(),
+            trace_context: telemetry::current_trace_context(),
+        });
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Result<BoxStream<'static, ReceivedMessage>, BoxError> {
+        let mut receiver = self.sender.subscribe();
+
+        let stream = async_stream::stream! {
+            loop {
+                match receiver.recv().await {
+                    Ok(message) => yield message,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        };
+
+        Ok(stream.boxed())
+    }
+
+    async fn check_health(&self) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::error::KafkaResult;
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::util::Timeout;
+use rdkafka::{ClientConfig, Message};
+use tracing::{error, instrument, warn};
+
+use common_utils::telemetry;
+
+use crate::event_bus::{BoxError, EventBus, ReceivedMessage};
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct KafkaEventBus {
+    broker: String,
+    topic: String,
+    producer: FutureProducer,
+}
+
+impl KafkaEventBus {
+    pub fn new(broker: String, topic: String) -> KafkaResult<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &broker)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        Ok(KafkaEventBus {
+            broker,
+            topic,
+            producer,
+        })
+    }
+
+    fn create_consumer(&self, group_id: String) -> KafkaResult<StreamConsumer> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &group_id)
+            .set("bootstrap.servers", &self.broker)
+            .set("enable.partition.eof", "false")
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "true")
+            .set_log_level(RDKafkaLogLevel::Debug)
+            .create()?;
+
+        consumer.subscribe(&[&self.topic])?;
+
+        Ok(consumer)
+    }
+}
+
+fn read_trace_context(message: &impl Message) -> HashMap<String, String> {
+    message
+        .headers()
+        .map(|headers| {
+            headers
+                .iter()
+                .filter_map(|header| {
+                    let value = String::from_utf8_lossy(header.value?).into_owned();
+                    Some((header.key.to_string(), value))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// each instance of the service should receive all messages, so it needs its own consumer group
+fn get_kafka_consumer_group_id() -> String {
+    let instance_id = std::env::var("HOSTNAME").unwrap_or_else(|_| std::process::id().to_string());
+    format!("graphql-group-{}", instance_id)
+}
+
+#[async_trait]
+impl EventBus for KafkaEventBus {
+    // TODO: send without caller blocking
+    #[instrument(name = "kafka_publish", skip(self, payload), fields(topic = %self.topic))]
+    async fn publish(&self, key: &str, payload: &[u8]) -> Result<(), BoxError> {
+        let headers = telemetry::current_trace_context().iter().fold(
+            OwnedHeaders::new(),
+            |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: Some(value.as_str()),
+                })
+            },
+        );
+
+        self.producer
+            .send(
+                FutureRecord::to(&self.topic)
+                    .payload(payload)
+                    .key(key)
+                    .headers(headers),
+                Timeout::After(Duration::from_secs(0)),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| e.into())
+    }
+
+    fn subscribe(&self) -> Result<BoxStream<'static, ReceivedMessage>, BoxError> {
+        let consumer = self.create_consumer(get_kafka_consumer_group_id())?;
+
+        let stream = async_stream::stream! {
+            let mut stream = consumer.stream();
+
+            while let Some(value) = stream.next().await {
+                match value {
+                    Ok(message) => match message.payload() {
+                        Some(payload) => yield ReceivedMessage {
+                            payload: payload.to_vec(),
+                            trace_context: read_trace_context(&message),
+                        },
+                        None => warn!("Kafka message doesn't contain payload"),
+                    },
+                    Err(e) => error!(error = %e, "Error while Kafka message processing"),
+                }
+            }
+        };
+
+        Ok(stream.boxed())
+    }
+
+    async fn check_health(&self) -> Result<(), BoxError> {
+        let producer = self.producer.clone();
+        let topic = self.topic.clone();
+        // fetching of metadata blocks, but it's the cheapest way to reach the broker
+        actix_rt::task::spawn_blocking(move || {
+            producer
+                .client()
+                .fetch_metadata(Some(&topic), HEALTH_CHECK_TIMEOUT)
+        })
+        .await??;
+        Ok(())
+    }
+}
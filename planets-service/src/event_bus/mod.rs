@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use tokio::sync::broadcast::Sender;
+use tracing::error;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use common_utils::telemetry;
+
+pub use in_memory::InMemoryEventBus;
+pub use kafka::KafkaEventBus;
+
+mod in_memory;
+mod kafka;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Clone)]
+pub struct ReceivedMessage {
+    pub payload: Vec<u8>,
+    /// Trace context of the publisher, so processing of the message continues its trace
+    pub trace_context: HashMap<String, String>,
+}
+
+/// Delivers messages from the service to all of its instances
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    /// Publishes a message along with trace context of the current span
+    async fn publish(&self, key: &str, payload: &[u8]) -> Result<(), BoxError>;
+
+    /// Returns a stream of all messages published after the subscription
+    fn subscribe(&self) -> Result<BoxStream<'static, ReceivedMessage>, BoxError>;
+
+    /// Checks that messages can be published at the moment
+    async fn check_health(&self) -> Result<(), BoxError>;
+}
+
+pub enum EventBusConfig {
+    Kafka { broker: String, topic: String },
+    InMemory,
+}
+
+impl EventBusConfig {
+    /// `EVENT_BUS_BACKEND` is either `kafka` (default) or `in-memory`
+    pub fn from_env() -> Result<Self, String> {
+        let backend = env::var("EVENT_BUS_BACKEND").unwrap_or_else(|_| "kafka".to_string());
+
+        match backend.as_str() {
+            "kafka" => Ok(EventBusConfig::Kafka {
+                broker: env::var("KAFKA_BROKER")
+                    .map_err(|_| "Can't read Kafka broker address".to_string())?,
+                topic: env::var("KAFKA_TOPIC")
+                    .map_err(|_| "Can't read Kafka topic name".to_string())?,
+            }),
+            "in-memory" => Ok(EventBusConfig::InMemory),
+            other => Err(format!("Unknown event bus backend: {}", other)),
+        }
+    }
+}
+
+pub fn create_event_bus(config: EventBusConfig) -> Result<Arc<dyn EventBus>, BoxError> {
+    match config {
+        EventBusConfig::Kafka { broker, topic } => Ok(Arc::new(KafkaEventBus::new(broker, topic)?)),
+        EventBusConfig::InMemory => Ok(Arc::new(InMemoryEventBus::default())),
+    }
+}
+
+/// Starts the only consumer of the application. It decodes messages from the bus
+/// and broadcasts them to all subscribers
+pub fn start_consumer<T>(event_bus: &dyn EventBus, sender: Sender<T>)
+where
+    T: DeserializeOwned + Clone + 'static,
+{
+    let mut stream = event_bus
+        .subscribe()
+        .expect("Can't subscribe to the event bus");
+
+    actix_rt::spawn(async move {
+        while let Some(message) = stream.next().await {
+            let span = tracing::info_span!("consume_event");
+            span.set_parent(telemetry::trace_context_from(&message.trace_context));
+            let _entered = span.enter();
+
+            match serde_json::from_slice::<T>(&message.payload) {
+                // an error only means that there are no subscribers at the moment
+                Ok(decoded_message) => {
+                    let _ = sender.send(decoded_message);
+                }
+                Err(e) => error!(error = %e, "Can't deserialize a message"),
+            }
+        }
+    });
+}
@@ -1,66 +1,139 @@
 use std::collections::HashMap;
-use std::env;
 use std::fmt::{self, Formatter, LowerExp};
 use std::iter::Iterator;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
+use async_graphql::connection::{query, Connection, OpaqueCursor};
 use async_graphql::dataloader::{DataLoader, Loader};
 use async_graphql::*;
 use bigdecimal::{BigDecimal, ToPrimitive};
+use diesel::OptionalExtension;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::AsyncConnection;
 use futures::{Stream, StreamExt};
-use rdkafka::{producer::FutureProducer, Message};
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Sender;
+use tracing::warn;
 
-use common_utils::{CustomError, Role, FORBIDDEN_MESSAGE};
+use common_utils::error::{self, ErrorCode, ServiceError, ServiceResult};
+use common_utils::metrics;
+use common_utils::pagination::{self, KeysetPage};
+use common_utils::permissions::PermissionGuard;
+use common_utils::telemetry;
 
 use crate::get_conn_from_ctx;
-use crate::kafka;
+use crate::outbox::OutboxNotifier;
 use crate::persistence::connection::PgPool;
-use crate::persistence::model::{DetailsEntity, NewDetailsEntity, NewPlanetEntity, PlanetEntity};
-use crate::persistence::repository;
+use crate::persistence::model::{
+    DetailsChangeset, DetailsEntity, NewDetailsEntity, NewOutboxMessageEntity, NewPlanetEntity,
+    PlanetChangeset, PlanetEntity,
+};
+use crate::persistence::repository::{self, PlanetFilterParams, PlanetSortField, PlanetSortKey};
+
+const PLANETS_WRITE: &str = "planets:write";
 
 pub type AppSchema = Schema<Query, Mutation, Subscription>;
 
+type PlanetCursor = OpaqueCursor<PlanetSortKey>;
+
 pub struct Query;
 
 #[Object]
 impl Query {
-    async fn get_planets(&self, ctx: &Context<'_>) -> Vec<Planet> {
-        repository::get_all(&mut get_conn_from_ctx(ctx))
-            .expect("Can't get planets")
-            .iter()
-            .map(Planet::from)
-            .collect()
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "pagination::page_complexity(first, last, child_complexity)")]
+    async fn get_planets(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<PlanetFilter>,
+        order_by: Option<PlanetOrder>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<PlanetCursor, Planet>> {
+        let filter = filter.map(PlanetFilterParams::from).unwrap_or_default();
+        let (sort_field, descending) = match order_by {
+            Some(order) => (
+                PlanetSortField::from(order.field),
+                order.direction == OrderDirection::Desc,
+            ),
+            None => (PlanetSortField::Id, false),
+        };
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = KeysetPage::<PlanetSortKey>::new(after, before, first, last)?;
+                if page
+                    .after
+                    .iter()
+                    .chain(&page.before)
+                    .any(|key| key.field() != sort_field)
+                {
+                    return Err(ServiceError::bad_user_input(
+                        "Cursor doesn't match the requested order",
+                    ));
+                }
+
+                let mut conn = get_conn_from_ctx(ctx).await?;
+                let planets_with_details =
+                    repository::get_page(&filter, sort_field, descending, &page, &mut conn).await?;
+                // details are already joined, so they aren't loaded again by `DetailsLoader`
+                let planets = planets_with_details
+                    .iter()
+                    .map(|planet_with_details| {
+                        let (planet, details) = planet_with_details;
+                        Ok((
+                            PlanetSortKey::new(sort_field, planet, details),
+                            Planet::try_from(planet_with_details)?,
+                        ))
+                    })
+                    .collect::<ServiceResult<Vec<_>>>()?;
+                Ok::<_, ServiceError>(page.into_connection(
+                    planets,
+                    |(key, _)| key.clone(),
+                    |(_, planet)| planet.clone(),
+                ))
+            },
+        )
+        .await
+        .map_err(|e| error::with_default_code(e, ErrorCode::BadUserInput))
     }
 
-    async fn get_planet(&self, ctx: &Context<'_>, id: ID) -> Option<Planet> {
-        find_planet_by_id_internal(ctx, id)
+    async fn get_planet(&self, ctx: &Context<'_>, id: ID) -> ServiceResult<Option<Planet>> {
+        find_planet_by_id_internal(ctx, id).await
     }
 
     #[graphql(entity)]
-    async fn find_planet_by_id(&self, ctx: &Context<'_>, id: ID) -> Option<Planet> {
-        find_planet_by_id_internal(ctx, id)
+    async fn find_planet_by_id(&self, ctx: &Context<'_>, id: ID) -> ServiceResult<Option<Planet>> {
+        find_planet_by_id_internal(ctx, id).await
     }
 }
 
-fn find_planet_by_id_internal(ctx: &Context<'_>, id: ID) -> Option<Planet> {
-    let id = id
-        .to_string()
-        .parse::<i32>()
-        .expect("Can't get id from String");
-    repository::get(id, &mut get_conn_from_ctx(ctx))
-        .ok()
-        .map(|p| Planet::from(&p))
+async fn find_planet_by_id_internal(ctx: &Context<'_>, id: ID) -> ServiceResult<Option<Planet>> {
+    let id = error::parse_id(&id)?;
+    let mut conn = get_conn_from_ctx(ctx).await?;
+    repository::get(id, &mut conn)
+        .await
+        .optional()?
+        .as_ref()
+        .map(Planet::try_from)
+        .transpose()
 }
 
 pub struct Mutation;
 
 #[Object]
 impl Mutation {
-    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
-    async fn create_planet(&self, ctx: &Context<'_>, planet: PlanetInput) -> Result<Planet> {
+    #[graphql(guard = "PermissionGuard::new(PLANETS_WRITE)")]
+    async fn create_planet(&self, ctx: &Context<'_>, planet: PlanetInput) -> ServiceResult<Planet> {
         let new_planet = NewPlanetEntity {
             name: planet.name,
             type_: planet.type_.to_string(),
@@ -69,23 +142,139 @@ impl Mutation {
         let details = planet.details;
         let new_planet_details = NewDetailsEntity {
             mean_radius: details.mean_radius.0,
-            mass: BigDecimal::from_str(&details.mass.0.to_string())
-                .expect("Can't get BigDecimal from string"),
+            mass: details.mass.0,
             population: details.population.map(|wrapper| wrapper.0),
             planet_id: 0,
         };
 
-        let created_planet_entity =
-            repository::create(new_planet, new_planet_details, &mut get_conn_from_ctx(ctx))?;
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let created_planet = conn
+            .transaction(move |conn| {
+                async move {
+                    let created_planet_entities =
+                        repository::create(new_planet, new_planet_details, conn).await?;
+                    let created_planet = Planet::try_from(&created_planet_entities)?;
+                    repository::create_outbox_message(
+                        new_planet_event_message(PlanetEventType::Created, &created_planet)?,
+                        conn,
+                    )
+                    .await?;
+                    Ok::<_, ServiceError>(created_planet)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        notify_outbox_relay(ctx);
+
+        Ok(created_planet)
+    }
+
+    #[graphql(guard = "PermissionGuard::new(PLANETS_WRITE)")]
+    async fn update_planet(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        planet: PlanetPatchInput,
+    ) -> ServiceResult<Planet> {
+        let id = error::parse_id(&id)?;
+
+        let planet_changeset = PlanetChangeset {
+            name: planet.name,
+            type_: planet.type_.map(|type_| type_.to_string()),
+        };
+
+        let details_changeset = match planet.details {
+            Some(details) => DetailsChangeset {
+                mean_radius: details.mean_radius.map(|wrapper| wrapper.0),
+                mass: details.mass.map(|wrapper| wrapper.0),
+                population: match details.population {
+                    MaybeUndefined::Undefined => None,
+                    MaybeUndefined::Null => Some(None),
+                    MaybeUndefined::Value(wrapper) => Some(Some(wrapper.0)),
+                },
+            },
+            None => DetailsChangeset {
+                mean_radius: None,
+                mass: None,
+                population: None,
+            },
+        };
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let updated_planet = conn
+            .transaction(move |conn| {
+                async move {
+                    let updated_planet_entity =
+                        repository::update(id, planet_changeset, details_changeset, conn).await?;
+                    let updated_planet = Planet::try_from(&updated_planet_entity)?;
+                    repository::create_outbox_message(
+                        new_planet_event_message(PlanetEventType::Updated, &updated_planet)?,
+                        conn,
+                    )
+                    .await?;
+                    Ok::<_, ServiceError>(updated_planet)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        notify_outbox_relay(ctx);
+
+        Ok(updated_planet)
+    }
+
+    /// Returns ID of the deleted planet
+    #[graphql(guard = "PermissionGuard::new(PLANETS_WRITE)")]
+    async fn delete_planet(&self, ctx: &Context<'_>, id: ID) -> ServiceResult<ID> {
+        let id = error::parse_id(&id)?;
+
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        let deleted_planet = conn
+            .transaction(move |conn| {
+                async move {
+                    let deleted_planet_entity = repository::delete(id, conn).await?;
+                    let deleted_planet = Planet::try_from(&deleted_planet_entity)?;
+                    repository::create_outbox_message(
+                        new_planet_event_message(PlanetEventType::Deleted, &deleted_planet)?,
+                        conn,
+                    )
+                    .await?;
+                    Ok::<_, ServiceError>(deleted_planet)
+                }
+                .scope_boxed()
+            })
+            .await?;
+        notify_outbox_relay(ctx);
+
+        Ok(deleted_planet.id)
+    }
+}
 
-        let producer = ctx
-            .data::<FutureProducer>()
-            .expect("Can't get Kafka producer");
-        let message = serde_json::to_string(&Planet::from(&created_planet_entity))
-            .expect("Can't serialize a planet");
-        kafka::send_message(producer, &message).await;
+/// An event is stored in the outbox within the transaction that changes a planet,
+/// and published by the relay after the transaction commits
+fn new_planet_event_message(
+    event_type: PlanetEventType,
+    planet: &Planet,
+) -> ServiceResult<NewOutboxMessageEntity> {
+    let event = PlanetEvent {
+        type_: event_type,
+        planet: planet.clone(),
+    };
+
+    let payload = serde_json::to_string(&event)
+        .map_err(|e| ServiceError::internal(format!("Can't serialize a planet event: {}", e)))?;
+
+    Ok(NewOutboxMessageEntity {
+        message_key: planet.id.to_string(),
+        payload,
+        // the relay publishes the message later, so the trace is continued from here
+        trace_context: serde_json::to_string(&telemetry::current_trace_context()).ok(),
+    })
+}
 
-        Ok(Planet::from(&created_planet_entity))
+/// The relay polls the outbox anyway, so a missing notifier only delays publishing
+fn notify_outbox_relay(ctx: &Context<'_>) {
+    if let Ok(notifier) = ctx.data::<OutboxNotifier>() {
+        notifier.notify();
     }
 }
 
@@ -96,38 +285,66 @@ impl Subscription {
     async fn latest_planet<'ctx>(
         &self,
         ctx: &'ctx Context<'_>,
-    ) -> impl Stream<Item = Planet> + 'ctx {
-        let kafka_consumer_counter = ctx
-            .data::<Mutex<i32>>()
-            .expect("Can't get Kafka consumer counter");
-        let consumer_group_id = kafka::get_kafka_consumer_group_id(kafka_consumer_counter);
-        // In fact, there should be only one Kafka consumer in this application. It should broadcast
-        // messages from a topic to each subscriber. For simplicity purposes a consumer is created per
-        // each subscription
-        let consumer = kafka::create_consumer(consumer_group_id);
-
-        async_stream::stream! {
-            let mut stream = consumer.stream();
-
-            while let Some(value) = stream.next().await {
-                yield match value {
-                    Ok(message) => {
-                        let payload = message.payload().expect("Kafka message should contain payload");
-                        let message = String::from_utf8_lossy(payload).to_string();
-                        serde_json::from_str(&message).expect("Can't deserialize a planet")
-                    }
-                    Err(e) => panic!("Error while Kafka message processing: {}", e)
-                };
+    ) -> Result<impl Stream<Item = Planet> + 'ctx, ServiceError> {
+        Ok(consume_planet_events(ctx)?.filter_map(|event| async move {
+            match event.type_ {
+                PlanetEventType::Created => Some(event.planet),
+                _ => None,
             }
-        }
+        }))
+    }
+
+    /// Creations, updates and deletions of planets
+    async fn planet_events<'ctx>(
+        &self,
+        ctx: &'ctx Context<'_>,
+    ) -> Result<impl Stream<Item = PlanetEvent> + 'ctx, ServiceError> {
+        consume_planet_events(ctx)
     }
 }
 
-#[derive(Serialize, Deserialize)]
+fn consume_planet_events<'ctx>(
+    ctx: &'ctx Context<'_>,
+) -> Result<impl Stream<Item = PlanetEvent> + 'ctx, ServiceError> {
+    let mut receiver = ctx.data::<Sender<PlanetEvent>>()?.subscribe();
+
+    Ok(async_stream::stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => yield event,
+                // a slow subscriber misses the oldest events but keeps receiving new ones
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Subscriber lagged behind, planet events were skipped")
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[derive(Clone, SimpleObject, Serialize, Deserialize)]
+pub struct PlanetEvent {
+    #[graphql(name = "type")]
+    type_: PlanetEventType,
+    planet: Planet,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum PlanetEventType {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct Planet {
     id: ID,
     name: String,
     type_: PlanetType,
+    /// Set if details are fetched together with a planet
+    #[serde(default)]
+    details: Option<Details>,
 }
 
 #[Object]
@@ -151,17 +368,15 @@ impl Planet {
         true
     }
 
-    async fn details(&self, ctx: &Context<'_>) -> Result<Details> {
-        let data_loader = ctx
-            .data::<DataLoader<DetailsLoader>>()
-            .expect("Can't get data loader");
-        let planet_id = self
-            .id
-            .to_string()
-            .parse::<i32>()
-            .expect("Can't convert id");
+    async fn details(&self, ctx: &Context<'_>) -> ServiceResult<Details> {
+        if let Some(details) = &self.details {
+            return Ok(details.clone());
+        }
+
+        let data_loader = ctx.data::<DataLoader<DetailsLoader>>()?;
+        let planet_id = error::parse_id(&self.id)?;
         let details = data_loader.load_one(planet_id).await?;
-        details.ok_or_else(|| "Not found".into())
+        details.ok_or_else(|| ServiceError::not_found("Details not found"))
     }
 }
 
@@ -174,7 +389,7 @@ enum PlanetType {
     DwarfPlanet,
 }
 
-#[derive(Interface, Clone)]
+#[derive(Interface, Clone, Serialize, Deserialize)]
 #[graphql(
     field(name = "mean_radius", ty = "&CustomBigDecimal"),
     field(name = "mass", ty = "&CustomBigInt")
@@ -184,7 +399,7 @@ pub enum Details {
     UninhabitedPlanetDetails(UninhabitedPlanetDetails),
 }
 
-#[derive(SimpleObject, Clone)]
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
 pub struct InhabitedPlanetDetails {
     mean_radius: CustomBigDecimal,
     mass: CustomBigInt,
@@ -192,13 +407,13 @@ pub struct InhabitedPlanetDetails {
     population: CustomBigDecimal,
 }
 
-#[derive(SimpleObject, Clone)]
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
 pub struct UninhabitedPlanetDetails {
     mean_radius: CustomBigDecimal,
     mass: CustomBigInt,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CustomBigInt(BigDecimal);
 
 #[Scalar(name = "BigInt")]
@@ -225,7 +440,7 @@ impl LowerExp for CustomBigInt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CustomBigDecimal(BigDecimal);
 
 #[Scalar(name = "BigDecimal")]
@@ -263,38 +478,150 @@ struct DetailsInput {
     population: Option<CustomBigDecimal>,
 }
 
-impl From<&PlanetEntity> for Planet {
-    fn from(entity: &PlanetEntity) -> Self {
-        Planet {
+#[derive(InputObject)]
+struct PlanetPatchInput {
+    name: Option<String>,
+    #[graphql(name = "type")]
+    type_: Option<PlanetType>,
+    details: Option<DetailsPatchInput>,
+}
+
+#[derive(InputObject)]
+struct DetailsPatchInput {
+    /// In kilometers
+    mean_radius: Option<CustomBigDecimal>,
+    /// In kilograms. A number should be represented as, for example, `6.42e+23`
+    mass: Option<CustomBigInt>,
+    /// In billions. Pass `null` to make a planet uninhabited
+    population: MaybeUndefined<CustomBigDecimal>,
+}
+
+#[derive(InputObject)]
+struct PlanetFilter {
+    /// Case-insensitive part of a name
+    name_contains: Option<String>,
+    types: Option<Vec<PlanetType>>,
+    /// In kilometers
+    mean_radius: Option<BigDecimalRange>,
+    /// In kilograms
+    mass: Option<BigIntRange>,
+    inhabited: Option<bool>,
+}
+
+/// Inclusive range
+#[derive(InputObject)]
+struct BigDecimalRange {
+    min: Option<CustomBigDecimal>,
+    max: Option<CustomBigDecimal>,
+}
+
+/// Inclusive range
+#[derive(InputObject)]
+struct BigIntRange {
+    min: Option<CustomBigInt>,
+    max: Option<CustomBigInt>,
+}
+
+#[derive(InputObject)]
+struct PlanetOrder {
+    field: PlanetOrderField,
+    #[graphql(default)]
+    direction: OrderDirection,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Enum)]
+enum PlanetOrderField {
+    Name,
+    MeanRadius,
+    Mass,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Default, Enum)]
+enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl From<PlanetFilter> for PlanetFilterParams {
+    fn from(filter: PlanetFilter) -> Self {
+        let (min_mean_radius, max_mean_radius) = match filter.mean_radius {
+            Some(range) => (range.min.map(|v| v.0), range.max.map(|v| v.0)),
+            None => (None, None),
+        };
+        let (min_mass, max_mass) = match filter.mass {
+            Some(range) => (range.min.map(|v| v.0), range.max.map(|v| v.0)),
+            None => (None, None),
+        };
+
+        PlanetFilterParams {
+            name_contains: filter.name_contains,
+            types: filter
+                .types
+                .map(|types| types.iter().map(PlanetType::to_string).collect()),
+            min_mean_radius,
+            max_mean_radius,
+            min_mass,
+            max_mass,
+            inhabited: filter.inhabited,
+        }
+    }
+}
+
+impl From<PlanetOrderField> for PlanetSortField {
+    fn from(field: PlanetOrderField) -> Self {
+        match field {
+            PlanetOrderField::Name => PlanetSortField::Name,
+            PlanetOrderField::MeanRadius => PlanetSortField::MeanRadius,
+            PlanetOrderField::Mass => PlanetSortField::Mass,
+        }
+    }
+}
+
+impl TryFrom<&PlanetEntity> for Planet {
+    type Error = ServiceError;
+
+    fn try_from(entity: &PlanetEntity) -> Result<Self, Self::Error> {
+        let type_ = PlanetType::from_str(entity.type_.as_str()).map_err(|_| {
+            ServiceError::internal(format!("Unknown planet type: {}", entity.type_))
+        })?;
+
+        Ok(Planet {
             id: entity.id.into(),
             name: entity.name.clone(),
-            type_: PlanetType::from_str(entity.type_.as_str())
-                .expect("Can't convert &str to PlanetType"),
-        }
+            type_,
+            details: None,
+        })
+    }
+}
+
+impl TryFrom<&(PlanetEntity, DetailsEntity)> for Planet {
+    type Error = ServiceError;
+
+    fn try_from(
+        (planet_entity, details_entity): &(PlanetEntity, DetailsEntity),
+    ) -> Result<Self, Self::Error> {
+        Ok(Planet {
+            details: Some(Details::from(details_entity)),
+            ..Planet::try_from(planet_entity)?
+        })
     }
 }
 
 impl From<&DetailsEntity> for Details {
     fn from(entity: &DetailsEntity) -> Self {
-        if entity.population.is_some() {
-            InhabitedPlanetDetails {
+        match &entity.population {
+            Some(population) => InhabitedPlanetDetails {
                 mean_radius: CustomBigDecimal(entity.mean_radius.clone()),
                 mass: CustomBigInt(entity.mass.clone()),
-                population: CustomBigDecimal(
-                    entity
-                        .population
-                        .as_ref()
-                        .expect("Can't get population")
-                        .clone(),
-                ),
+                population: CustomBigDecimal(population.clone()),
             }
-            .into()
-        } else {
-            UninhabitedPlanetDetails {
+            .into(),
+            None => UninhabitedPlanetDetails {
                 mean_radius: CustomBigDecimal(entity.mean_radius.clone()),
                 mass: CustomBigInt(entity.mass.clone()),
             }
-            .into()
+            .into(),
         }
     }
 }
@@ -305,11 +632,12 @@ pub struct DetailsLoader {
 
 impl Loader<i32> for DetailsLoader {
     type Value = Details;
-    type Error = Error;
+    type Error = ServiceError;
 
     async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
-        let mut conn = self.pool.get()?;
-        let details = repository::get_details(keys, &mut conn)?;
+        metrics::observe_dataloader_batch("details", keys.len());
+        let mut conn = self.pool.get().await?;
+        let details = repository::get_details(keys, &mut conn).await?;
 
         Ok(details
             .iter()
@@ -317,38 +645,3 @@ impl Loader<i32> for DetailsLoader {
             .collect::<HashMap<_, _>>())
     }
 }
-
-struct RoleGuard {
-    role: Role,
-}
-
-impl RoleGuard {
-    fn new(role: Role) -> Self {
-        Self { role }
-    }
-}
-
-impl Guard for RoleGuard {
-    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        // TODO: auth disabling is needed for tests. try to reimplement when https://github.com/rust-lang/rust/issues/45599 will be resolved (using cfg(test))
-        if let Ok(boolean) = env::var("DISABLE_AUTH") {
-            let disable_auth = bool::from_str(boolean.as_str()).expect("Can't parse bool");
-            if disable_auth {
-                return Ok(());
-            }
-        };
-
-        let maybe_getting_role_result = ctx.data_opt::<Result<Option<Role>, CustomError>>();
-        match maybe_getting_role_result {
-            Some(getting_role_result) => {
-                let check_role_result =
-                    common_utils::check_user_role_is_allowed(getting_role_result, &self.role);
-                match check_role_result {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(Error::new(e.message)),
-                }
-            }
-            None => Err(FORBIDDEN_MESSAGE.into()),
-        }
-    }
-}
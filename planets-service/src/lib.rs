@@ -1,24 +1,45 @@
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use actix_web::{guard, web, HttpRequest, HttpResponse, Result};
 use async_graphql::dataloader::DataLoader;
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql::{Context, Schema};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
-use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::PgConnection;
+use diesel_async::pooled_connection::deadpool::Object;
+use diesel_async::AsyncPgConnection;
 use diesel_migrations::MigrationHarness;
+use futures::join;
+use tokio::sync::broadcast;
+use tracing::Instrument;
 
-use crate::graphql::{AppSchema, DetailsLoader, Mutation, Query, Subscription};
+use common_utils::error::ServiceResult;
+use common_utils::health::{self, DependencyHealth, HealthReport};
+use common_utils::identity::Identity;
+use common_utils::metrics::{self, GraphQLMetrics};
+use common_utils::permissions::AuthPolicy;
+use common_utils::persisted_queries::{PersistedQueries, PersistedQueriesConfig};
+use common_utils::query_limits::QueryLimits;
+use common_utils::request_id::{RequestId, RequestIdInErrors};
+use common_utils::telemetry::{self, GraphQLTracing};
+
+use crate::event_bus::EventBus;
+use crate::graphql::{AppSchema, DetailsLoader, Mutation, PlanetEvent, Query, Subscription};
+use crate::outbox::OutboxNotifier;
 use crate::persistence::connection::PgPool;
 
+pub mod event_bus;
 pub mod graphql;
-mod kafka;
+pub mod outbox;
 pub mod persistence;
 
 const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
     diesel_migrations::embed_migrations!("./migrations");
 
+// how many events a subscriber can fall behind before it starts to miss them
+const PLANET_EVENTS_CHANNEL_CAPACITY: usize = 1024;
+
 pub fn configure_service(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/")
@@ -30,17 +51,27 @@ pub fn configure_service(cfg: &mut web::ServiceConfig) {
             )
             .route(web::get().to(index_playground)),
     );
+    cfg.route("/metrics", web::get().to(metrics::metrics));
+    cfg.route("/health/live", web::get().to(health::live));
+    cfg.route("/health/ready", web::get().to(readiness));
 }
 
 async fn index(
     schema: web::Data<AppSchema>,
     http_req: HttpRequest,
+    request_id: Option<web::ReqData<RequestId>>,
+    identity: Option<web::ReqData<Identity>>,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
+    let span = telemetry::request_span(&http_req);
     let mut query = req.into_inner();
-    let getting_role_result = common_utils::get_role(http_req);
-    query = query.data(getting_role_result);
-    schema.execute(query).await.into()
+    if let Some(request_id) = request_id {
+        query = query.data(request_id.into_inner());
+    }
+    if let Some(identity) = identity {
+        query = query.data(identity.into_inner().permissions);
+    }
+    schema.execute(query).instrument(span).await.into()
 }
 
 async fn index_ws(
@@ -51,6 +82,18 @@ async fn index_ws(
     GraphQLSubscription::new(Schema::clone(&*schema)).start(&req, payload)
 }
 
+async fn readiness(pool: web::Data<PgPool>, event_bus: web::Data<dyn EventBus>) -> HttpResponse {
+    let (database, event_bus) = join!(
+        health::check_database(&pool),
+        DependencyHealth::check(event_bus.check_health())
+    );
+    HealthReport::new(BTreeMap::from([
+        ("database", database),
+        ("event_bus", event_bus),
+    ]))
+    .into_response()
+}
+
 async fn index_playground() -> HttpResponse {
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
@@ -59,34 +102,46 @@ async fn index_playground() -> HttpResponse {
         ))
 }
 
-pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, Mutation, Subscription> {
+pub fn create_schema_with_context(
+    pool: PgPool,
+    event_bus: Arc<dyn EventBus>,
+    auth_policy: Arc<dyn AuthPolicy>,
+) -> Schema<Query, Mutation, Subscription> {
     let arc_pool = Arc::new(pool);
     let cloned_pool = Arc::clone(&arc_pool);
     let details_data_loader =
         DataLoader::new(DetailsLoader { pool: cloned_pool }, actix_rt::spawn).max_batch_size(10);
 
-    let kafka_consumer_counter = Mutex::new(0);
+    let (planet_events_sender, _) =
+        broadcast::channel::<PlanetEvent>(PLANET_EVENTS_CHANNEL_CAPACITY);
+    event_bus::start_consumer(event_bus.as_ref(), planet_events_sender.clone());
+
+    let outbox_notifier = OutboxNotifier::default();
+    outbox::start_relay(Arc::clone(&arc_pool), event_bus, outbox_notifier.clone());
 
     Schema::build(Query, Mutation, Subscription)
-        // limits are commented out, because otherwise introspection query won't work
-        // .limit_depth(3)
-        // .limit_complexity(15)
+        .extension(GraphQLMetrics)
+        .extension(GraphQLTracing)
+        .extension(RequestIdInErrors)
+        .extension(PersistedQueries::new(
+            &PersistedQueriesConfig::from_env().expect("Can't configure persisted queries"),
+            PgPool::clone(&arc_pool),
+        ))
+        .extension(QueryLimits::from_env().expect("Can't configure query limits"))
         .data(arc_pool)
         .data(details_data_loader)
-        .data(kafka::create_producer())
-        .data(kafka_consumer_counter)
+        .data(planet_events_sender)
+        .data(outbox_notifier)
+        .data(auth_policy)
         .enable_subscription_in_federation()
         .finish()
 }
 
-pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
+pub fn run_migrations(conn: &mut PgConnection) {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("Failed to run database migrations");
 }
 
-pub fn get_conn_from_ctx(ctx: &Context<'_>) -> PooledConnection<ConnectionManager<PgConnection>> {
-    ctx.data::<Arc<PgPool>>()
-        .expect("Can't get pool")
-        .get()
-        .expect("Can't get DB connection")
+pub async fn get_conn_from_ctx(ctx: &Context<'_>) -> ServiceResult<Object<AsyncPgConnection>> {
+    Ok(ctx.data::<Arc<PgPool>>()?.get().await?)
 }
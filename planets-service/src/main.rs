@@ -1,29 +1,63 @@
 extern crate planets_service;
 
 use std::env;
+use std::sync::Arc;
 
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
 
-use planets_service::persistence::connection::create_connection_pool;
+use common_utils::identity::{verify_identity, IdentityKey};
+use common_utils::metrics::track_http_requests;
+use common_utils::permissions::EnforcingPolicy;
+use common_utils::request_id::assign_request_id;
+use common_utils::telemetry::{self, TraceExporter};
+
+use planets_service::event_bus::{create_event_bus, EventBusConfig};
+use planets_service::persistence::connection::{
+    create_connection_pool, establish_migration_connection,
+};
 use planets_service::{configure_service, create_schema_with_context, run_migrations};
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let pool = create_connection_pool();
-    run_migrations(&mut pool.get().expect("Can't get DB connection"));
+    let trace_exporter = TraceExporter::from_env().expect("Can't configure tracing");
+    telemetry::init_tracing("planets-service", trace_exporter).expect("Can't initialize tracing");
+    let pool = create_connection_pool().await;
+    run_migrations(&mut establish_migration_connection());
+
+    let event_bus_config = EventBusConfig::from_env().expect("Can't configure event bus");
+    let event_bus = create_event_bus(event_bus_config).expect("Can't create event bus");
 
-    let schema = web::Data::new(create_schema_with_context(pool));
+    let identity_key =
+        web::Data::new(IdentityKey::from_env().expect("Can't configure internal identity key"));
+    let schema = web::Data::new(create_schema_with_context(
+        pool.clone(),
+        Arc::clone(&event_bus),
+        Arc::new(EnforcingPolicy),
+    ));
+
+    let pool = web::Data::new(pool);
+    let event_bus = web::Data::from(event_bus);
 
     let server_port = env::var("SERVER_PORT").expect("Can't get server port");
 
     HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(verify_identity))
+            .wrap(from_fn(track_http_requests))
+            .wrap(from_fn(assign_request_id))
             .configure(configure_service)
             .app_data(schema.clone())
+            .app_data(identity_key.clone())
+            .app_data(pool.clone())
+            .app_data(event_bus.clone())
     })
     .bind(format!("0.0.0.0:{}", server_port))?
     .run()
-    .await
+    .await?;
+
+    telemetry::shutdown_tracing();
+    Ok(())
 }
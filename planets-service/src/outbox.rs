@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tracing::{debug, error, warn, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use common_utils::telemetry;
+
+use crate::event_bus::{BoxError, EventBus};
+use crate::persistence::connection::PgPool;
+use crate::persistence::repository;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: i64 = 100;
+// how long a leased message is hidden from other relays
+const LEASE_SECS: i64 = 30;
+const MAX_RETRY_DELAY_SECS: i64 = 60;
+// delivered messages are kept for a while to help investigate problems with delivery
+const RETENTION_HOURS: i64 = 24;
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Wakes the relay up after a message is stored, so it isn't published with a poll delay
+#[derive(Clone, Default)]
+pub struct OutboxNotifier(Arc<Notify>);
+
+impl OutboxNotifier {
+    pub fn notify(&self) {
+        self.0.notify_one();
+    }
+}
+
+/// Spawns a task that publishes pending outbox messages to the event bus
+/// and marks them as delivered. Delivery is at-least-once.
+/// Delivered messages are deleted once they are older than the retention period
+pub fn start_relay(pool: Arc<PgPool>, event_bus: Arc<dyn EventBus>, notifier: OutboxNotifier) {
+    actix_rt::spawn(async move {
+        let mut last_cleanup: Option<Instant> = None;
+        loop {
+            if last_cleanup.is_none_or(|cleanup| cleanup.elapsed() >= CLEANUP_INTERVAL) {
+                match delete_delivered_messages(&pool).await {
+                    Ok(deleted) => debug!(deleted, "Delivered outbox messages were deleted"),
+                    Err(e) => error!(error = %e, "Delivered outbox messages weren't deleted"),
+                }
+                last_cleanup = Some(Instant::now());
+            }
+
+            match relay_pending_messages(&pool, event_bus.as_ref()).await {
+                // the batch was full, so there may be more pending messages
+                Ok(relayed) if relayed as i64 == BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => error!(error = %e, "Outbox messages weren't relayed"),
+            }
+            let _ = tokio::time::timeout(POLL_INTERVAL, notifier.0.notified()).await;
+        }
+    });
+}
+
+async fn relay_pending_messages(
+    pool: &PgPool,
+    event_bus: &dyn EventBus,
+) -> Result<usize, BoxError> {
+    let mut conn = pool.get().await?;
+    let messages = repository::lease_outbox_messages(BATCH_SIZE, LEASE_SECS, &mut conn).await?;
+
+    let mut relayed = 0;
+    for (i, message) in messages.iter().enumerate() {
+        let span = tracing::info_span!("relay_outbox_message", message_id = message.id);
+        if let Some(trace_context) = message
+            .trace_context
+            .as_deref()
+            .and_then(|trace_context| serde_json::from_str(trace_context).ok())
+        {
+            span.set_parent(telemetry::trace_context_from(&trace_context));
+        }
+
+        match event_bus
+            .publish(&message.message_key, message.payload.as_bytes())
+            .instrument(span)
+            .await
+        {
+            Ok(_) => {
+                repository::mark_outbox_message_delivered(message.id, &mut conn).await?;
+                relayed += 1;
+            }
+            Err(e) => {
+                warn!(error = %e, message_id = message.id, "Message wasn't sent");
+                let retry_delay_secs = retry_delay_secs(message.attempts);
+                repository::mark_outbox_message_failed(
+                    message.id,
+                    &e.to_string(),
+                    retry_delay_secs,
+                    &mut conn,
+                )
+                .await?;
+                // the event bus is likely unavailable, so the rest of the batch is released
+                // to be retried together with the failed message and in the same order
+                let rest = messages[i + 1..].iter().map(|message| message.id).collect();
+                repository::release_outbox_messages(rest, retry_delay_secs, &mut conn).await?;
+                break;
+            }
+        }
+    }
+
+    Ok(relayed)
+}
+
+async fn delete_delivered_messages(pool: &PgPool) -> Result<usize, BoxError> {
+    let mut conn = pool.get().await?;
+    Ok(repository::delete_delivered_outbox_messages(RETENTION_HOURS, &mut conn).await?)
+}
+
+/// Exponential backoff: 1, 2, 4, ... seconds, but no more than a minute
+fn retry_delay_secs(attempts: i32) -> i64 {
+    2_i64
+        .saturating_pow(attempts.max(0) as u32)
+        .min(MAX_RETRY_DELAY_SECS)
+}
@@ -1,14 +1,24 @@
 use std::env;
 
-use diesel::pg::PgConnection;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::{Connection, PgConnection};
 
-pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+use common_utils::db::{self, PoolConfig};
 
-pub fn create_connection_pool() -> PgPool {
-    let db_url = env::var("DATABASE_URL").expect("Can't get DB URL");
-    let manager = ConnectionManager::<PgConnection>::new(db_url);
-    Pool::builder()
-        .build(manager)
+pub use common_utils::db::PgPool;
+
+/// Waits until the database is reachable, see [`db::create_pool`]
+pub async fn create_connection_pool() -> PgPool {
+    let config = PoolConfig::from_env().expect("Can't configure DB pool");
+    db::create_pool(&get_db_url(), &config)
+        .await
         .expect("Failed to create pool")
 }
+
+/// diesel_migrations works only with a synchronous connection
+pub fn establish_migration_connection() -> PgConnection {
+    PgConnection::establish(&get_db_url()).expect("Can't connect to DB")
+}
+
+fn get_db_url() -> String {
+    env::var("DATABASE_URL").expect("Can't get DB URL")
+}
@@ -1,7 +1,8 @@
 use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
-use crate::persistence::schema::{details, planets};
+use crate::persistence::schema::{details, outbox, planets};
 
 #[derive(Identifiable, Queryable)]
 #[diesel(table_name = planets)]
@@ -38,3 +39,54 @@ pub struct NewDetailsEntity {
     pub population: Option<BigDecimal>,
     pub planet_id: i32,
 }
+
+#[derive(AsChangeset)]
+#[diesel(table_name = planets)]
+pub struct PlanetChangeset {
+    pub name: Option<String>,
+    pub type_: Option<String>,
+}
+
+impl PlanetChangeset {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.type_.is_none()
+    }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = details)]
+pub struct DetailsChangeset {
+    pub mean_radius: Option<BigDecimal>,
+    pub mass: Option<BigDecimal>,
+    // `Some(None)` sets population to null
+    pub population: Option<Option<BigDecimal>>,
+}
+
+impl DetailsChangeset {
+    pub fn is_empty(&self) -> bool {
+        self.mean_radius.is_none() && self.mass.is_none() && self.population.is_none()
+    }
+}
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = outbox)]
+pub struct OutboxMessageEntity {
+    pub id: i32,
+    pub message_key: String,
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    /// Serialized trace context of the operation that created the message
+    pub trace_context: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = outbox)]
+pub struct NewOutboxMessageEntity {
+    pub message_key: String,
+    pub payload: String,
+    pub trace_context: Option<String>,
+}
@@ -1,40 +1,393 @@
+use bigdecimal::BigDecimal;
+use diesel::dsl::{exists, not, now, IntervalDsl};
+use diesel::helper_types::InnerJoinQuerySource;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
 
-use crate::persistence::model::{DetailsEntity, NewDetailsEntity, NewPlanetEntity, PlanetEntity};
-use crate::persistence::schema::{details, planets};
+use common_utils::pagination::KeysetPage;
 
-pub fn get_all(conn: &mut PgConnection) -> QueryResult<Vec<PlanetEntity>> {
-    use crate::persistence::schema::planets::dsl::*;
+use crate::persistence::model::{
+    DetailsChangeset, DetailsEntity, NewDetailsEntity, NewOutboxMessageEntity, NewPlanetEntity,
+    OutboxMessageEntity, PlanetChangeset, PlanetEntity,
+};
+use crate::persistence::schema::{details, outbox, planets};
 
-    planets.load(conn)
+#[derive(Default)]
+pub struct PlanetFilterParams {
+    pub name_contains: Option<String>,
+    pub types: Option<Vec<String>>,
+    pub min_mean_radius: Option<BigDecimal>,
+    pub max_mean_radius: Option<BigDecimal>,
+    pub min_mass: Option<BigDecimal>,
+    pub max_mass: Option<BigDecimal>,
+    pub inhabited: Option<bool>,
 }
 
-pub fn get(id: i32, conn: &mut PgConnection) -> QueryResult<PlanetEntity> {
-    planets::table.find(id).get_result(conn)
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum PlanetSortField {
+    Id,
+    Name,
+    MeanRadius,
+    Mass,
 }
 
-pub fn get_details(planet_ids: &[i32], conn: &mut PgConnection) -> QueryResult<Vec<DetailsEntity>> {
+/// Position of a planet in a sorted list: a value of the sort field and an ID as a tiebreaker
+#[derive(Clone, Serialize, Deserialize)]
+pub enum PlanetSortKey {
+    Id(i32),
+    Name(String, i32),
+    MeanRadius(BigDecimal, i32),
+    Mass(BigDecimal, i32),
+}
+
+impl PlanetSortKey {
+    pub fn new(field: PlanetSortField, planet: &PlanetEntity, details: &DetailsEntity) -> Self {
+        match field {
+            PlanetSortField::Id => PlanetSortKey::Id(planet.id),
+            PlanetSortField::Name => PlanetSortKey::Name(planet.name.clone(), planet.id),
+            PlanetSortField::MeanRadius => {
+                PlanetSortKey::MeanRadius(details.mean_radius.clone(), planet.id)
+            }
+            PlanetSortField::Mass => PlanetSortKey::Mass(details.mass.clone(), planet.id),
+        }
+    }
+
+    pub fn field(&self) -> PlanetSortField {
+        match self {
+            PlanetSortKey::Id(_) => PlanetSortField::Id,
+            PlanetSortKey::Name(..) => PlanetSortField::Name,
+            PlanetSortKey::MeanRadius(..) => PlanetSortField::MeanRadius,
+            PlanetSortKey::Mass(..) => PlanetSortField::Mass,
+        }
+    }
+}
+
+type PlanetsWithDetails = InnerJoinQuerySource<planets::table, details::table>;
+
+#[instrument(skip_all)]
+pub async fn get_page(
+    filter: &PlanetFilterParams,
+    sort_field: PlanetSortField,
+    descending: bool,
+    page: &KeysetPage<PlanetSortKey>,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Vec<(PlanetEntity, DetailsEntity)>> {
+    let mut query = planets::table.inner_join(details::table).into_boxed();
+
+    if let Some(name) = &filter.name_contains {
+        query = query.filter(planets::name.ilike(format!("%{}%", escape_like_pattern(name))));
+    }
+    if let Some(types) = &filter.types {
+        query = query.filter(planets::type_.eq_any(types));
+    }
+    if let Some(min_mean_radius) = &filter.min_mean_radius {
+        query = query.filter(details::mean_radius.ge(min_mean_radius));
+    }
+    if let Some(max_mean_radius) = &filter.max_mean_radius {
+        query = query.filter(details::mean_radius.le(max_mean_radius));
+    }
+    if let Some(min_mass) = &filter.min_mass {
+        query = query.filter(details::mass.ge(min_mass));
+    }
+    if let Some(max_mass) = &filter.max_mass {
+        query = query.filter(details::mass.le(max_mass));
+    }
+    if let Some(inhabited) = filter.inhabited {
+        query = if inhabited {
+            query.filter(details::population.is_not_null())
+        } else {
+            query.filter(details::population.is_null())
+        };
+    }
+
+    // "after" means "greater" in ascending order and "less" in descending one
+    if let Some(after) = &page.after {
+        query = query.filter(seek(after, !descending));
+    }
+    if let Some(before) = &page.before {
+        query = query.filter(seek(before, descending));
+    }
+
+    // rows are fetched in reverse order if a client paginates backwards
+    let ascending = descending == page.backward;
+    query = match (sort_field, ascending) {
+        (PlanetSortField::Id, true) => query.order(planets::id.asc()),
+        (PlanetSortField::Id, false) => query.order(planets::id.desc()),
+        (PlanetSortField::Name, true) => query.order((planets::name.asc(), planets::id.asc())),
+        (PlanetSortField::Name, false) => query.order((planets::name.desc(), planets::id.desc())),
+        (PlanetSortField::MeanRadius, true) => {
+            query.order((details::mean_radius.asc(), planets::id.asc()))
+        }
+        (PlanetSortField::MeanRadius, false) => {
+            query.order((details::mean_radius.desc(), planets::id.desc()))
+        }
+        (PlanetSortField::Mass, true) => query.order((details::mass.asc(), planets::id.asc())),
+        (PlanetSortField::Mass, false) => query.order((details::mass.desc(), planets::id.desc())),
+    };
+
+    query.limit(page.fetch_limit()).load(conn).await
+}
+
+/// Selects rows that are greater (or less) than the key in terms of `(sort field, id)`
+fn seek(
+    key: &PlanetSortKey,
+    greater: bool,
+) -> Box<dyn BoxableExpression<PlanetsWithDetails, Pg, SqlType = Bool>> {
+    match (key.clone(), greater) {
+        (PlanetSortKey::Id(id), true) => Box::new(planets::id.gt(id)),
+        (PlanetSortKey::Id(id), false) => Box::new(planets::id.lt(id)),
+        (PlanetSortKey::Name(name, id), true) => Box::new(
+            planets::name
+                .gt(name.clone())
+                .or(planets::name.eq(name).and(planets::id.gt(id))),
+        ),
+        (PlanetSortKey::Name(name, id), false) => Box::new(
+            planets::name
+                .lt(name.clone())
+                .or(planets::name.eq(name).and(planets::id.lt(id))),
+        ),
+        (PlanetSortKey::MeanRadius(mean_radius, id), true) => Box::new(
+            details::mean_radius
+                .gt(mean_radius.clone())
+                .or(details::mean_radius.eq(mean_radius).and(planets::id.gt(id))),
+        ),
+        (PlanetSortKey::MeanRadius(mean_radius, id), false) => Box::new(
+            details::mean_radius
+                .lt(mean_radius.clone())
+                .or(details::mean_radius.eq(mean_radius).and(planets::id.lt(id))),
+        ),
+        (PlanetSortKey::Mass(mass, id), true) => Box::new(
+            details::mass
+                .gt(mass.clone())
+                .or(details::mass.eq(mass).and(planets::id.gt(id))),
+        ),
+        (PlanetSortKey::Mass(mass, id), false) => Box::new(
+            details::mass
+                .lt(mass.clone())
+                .or(details::mass.eq(mass).and(planets::id.lt(id))),
+        ),
+    }
+}
+
+fn escape_like_pattern(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[instrument(skip_all)]
+pub async fn get(id: i32, conn: &mut AsyncPgConnection) -> QueryResult<PlanetEntity> {
+    planets::table.find(id).get_result(conn).await
+}
+
+#[instrument(skip_all)]
+pub async fn get_details(
+    planet_ids: &[i32],
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Vec<DetailsEntity>> {
     details::table
         .filter(details::planet_id.eq_any(planet_ids))
         .load::<DetailsEntity>(conn)
+        .await
 }
 
-pub fn create(
+#[instrument(skip_all)]
+pub async fn create(
     new_planet: NewPlanetEntity,
     mut new_details_entity: NewDetailsEntity,
-    conn: &mut PgConnection,
-) -> QueryResult<PlanetEntity> {
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<(PlanetEntity, DetailsEntity)> {
     use crate::persistence::schema::{details::dsl::*, planets::dsl::*};
 
-    let created_planet: PlanetEntity = diesel::insert_into(planets)
-        .values(new_planet)
-        .get_result(conn)?;
+    conn.transaction(move |conn| {
+        async move {
+            let created_planet: PlanetEntity = diesel::insert_into(planets)
+                .values(new_planet)
+                .get_result(conn)
+                .await?;
+
+            new_details_entity.planet_id = created_planet.id;
+
+            let created_details: DetailsEntity = diesel::insert_into(details)
+                .values(new_details_entity)
+                .get_result(conn)
+                .await?;
 
-    new_details_entity.planet_id = created_planet.id;
+            Ok((created_planet, created_details))
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[instrument(skip_all)]
+pub async fn update(
+    planet_id: i32,
+    planet_changeset: PlanetChangeset,
+    details_changeset: DetailsChangeset,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<PlanetEntity> {
+    conn.transaction(move |conn| {
+        async move {
+            // Diesel can't execute an update without changes
+            let updated_planet = if planet_changeset.is_empty() {
+                planets::table.find(planet_id).get_result(conn).await?
+            } else {
+                diesel::update(planets::table.find(planet_id))
+                    .set(planet_changeset)
+                    .get_result(conn)
+                    .await?
+            };
+
+            if !details_changeset.is_empty() {
+                diesel::update(details::table.filter(details::planet_id.eq(planet_id)))
+                    .set(details_changeset)
+                    .execute(conn)
+                    .await?;
+            }
+
+            Ok(updated_planet)
+        }
+        .scope_boxed()
+    })
+    .await
+}
 
-    diesel::insert_into(details)
-        .values(new_details_entity)
-        .execute(conn)?;
+#[instrument(skip_all)]
+pub async fn delete(planet_id: i32, conn: &mut AsyncPgConnection) -> QueryResult<PlanetEntity> {
+    conn.transaction(move |conn| {
+        async move {
+            diesel::delete(details::table.filter(details::planet_id.eq(planet_id)))
+                .execute(conn)
+                .await?;
+            diesel::delete(planets::table.find(planet_id))
+                .get_result(conn)
+                .await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[instrument(skip_all)]
+pub async fn create_outbox_message(
+    new_message: NewOutboxMessageEntity,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<OutboxMessageEntity> {
+    diesel::insert_into(outbox::table)
+        .values(new_message)
+        .get_result(conn)
+        .await
+}
+
+#[instrument(skip_all)]
+pub async fn get_outbox_message(
+    id: i32,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<OutboxMessageEntity> {
+    outbox::table.find(id).first(conn).await
+}
+
+/// Selects pending messages and postpones their next attempt for the duration of the lease,
+/// so other instances of the service don't pick them up concurrently
+#[instrument(skip_all)]
+pub async fn lease_outbox_messages(
+    limit: i64,
+    lease_secs: i64,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Vec<OutboxMessageEntity>> {
+    conn.transaction(move |conn| {
+        async move {
+            let earlier = diesel::alias!(outbox as earlier);
+            let ids: Vec<i32> = outbox::table
+                .select(outbox::id)
+                .filter(outbox::delivered_at.is_null())
+                .filter(outbox::next_attempt_at.le(now))
+                // messages of an aggregate are published in order, so a message waits
+                // while an earlier one with the same key is leased or retried
+                .filter(not(exists(
+                    earlier
+                        .filter(earlier.field(outbox::message_key).eq(outbox::message_key))
+                        .filter(earlier.field(outbox::id).lt(outbox::id))
+                        .filter(earlier.field(outbox::delivered_at).is_null())
+                        .filter(earlier.field(outbox::next_attempt_at).gt(now)),
+                )))
+                .order(outbox::id.asc())
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .load(conn)
+                .await?;
+
+            let mut messages: Vec<OutboxMessageEntity> =
+                diesel::update(outbox::table.filter(outbox::id.eq_any(ids)))
+                    .set(outbox::next_attempt_at.eq(now + lease_secs.seconds()))
+                    .get_results(conn)
+                    .await?;
+            messages.sort_by_key(|message| message.id);
+
+            Ok(messages)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[instrument(skip_all)]
+pub async fn mark_outbox_message_delivered(
+    id: i32,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::update(outbox::table.find(id))
+        .set(outbox::delivered_at.eq(now))
+        .execute(conn)
+        .await
+}
+
+/// Deletes messages delivered more than `retention_hours` ago
+#[instrument(skip_all)]
+pub async fn delete_delivered_outbox_messages(
+    retention_hours: i64,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::delete(
+        outbox::table.filter(outbox::delivered_at.lt((now - retention_hours.hours()).nullable())),
+    )
+    .execute(conn)
+    .await
+}
+
+#[instrument(skip_all)]
+pub async fn mark_outbox_message_failed(
+    id: i32,
+    error: &str,
+    retry_delay_secs: i64,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::update(outbox::table.find(id))
+        .set((
+            outbox::attempts.eq(outbox::attempts + 1),
+            outbox::last_error.eq(error),
+            outbox::next_attempt_at.eq(now + retry_delay_secs.seconds()),
+        ))
+        .execute(conn)
+        .await
+}
 
-    Ok(created_planet)
+#[instrument(skip_all)]
+pub async fn release_outbox_messages(
+    ids: Vec<i32>,
+    retry_delay_secs: i64,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<usize> {
+    diesel::update(outbox::table.filter(outbox::id.eq_any(ids)))
+        .set(outbox::next_attempt_at.eq(now + retry_delay_secs.seconds()))
+        .execute(conn)
+        .await
 }
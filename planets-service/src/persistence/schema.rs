@@ -8,6 +8,20 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    outbox (id) {
+        id -> Int4,
+        message_key -> Varchar,
+        payload -> Text,
+        created_at -> Timestamp,
+        attempts -> Int4,
+        last_error -> Nullable<Varchar>,
+        next_attempt_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+        trace_context -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     planets (id) {
         id -> Int4,
@@ -19,4 +33,4 @@ diesel::table! {
 
 diesel::joinable!(details -> planets (planet_id));
 
-diesel::allow_tables_to_appear_in_same_query!(details, planets,);
+diesel::allow_tables_to_appear_in_same_query!(details, outbox, planets,);
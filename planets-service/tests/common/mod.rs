@@ -6,14 +6,16 @@ use testcontainers::clients::Cli;
 use testcontainers::images::postgres::Postgres;
 use testcontainers::{Container, RunnableImage};
 
-use planets_service::persistence::connection::{create_connection_pool, PgPool};
+use planets_service::persistence::connection::{
+    create_connection_pool, establish_migration_connection, PgPool,
+};
 use planets_service::run_migrations;
 
-pub fn setup(docker: &Cli) -> (Container<Postgres>, PgPool) {
+pub async fn setup(docker: &Cli) -> (Container<Postgres>, PgPool) {
     dotenv().ok();
     let pg_container = setup_database(docker);
-    let pool = create_connection_pool();
-    run_migrations(&mut pool.get().expect("Can't get DB connection"));
+    let pool = create_connection_pool().await;
+    run_migrations(&mut establish_migration_connection());
     (pg_container, pool)
 }
 
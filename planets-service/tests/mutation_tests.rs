@@ -1,25 +1,34 @@
-use std::env;
+use std::sync::Arc;
 
 use actix_web::{test, web, App};
+use futures::StreamExt;
 use jsonpath_lib as jsonpath;
 use serde::{Deserialize, Serialize};
 use serde_json::Map;
 use testcontainers::clients::Cli;
 
+use common_utils::permissions::{EnforcingPolicy, PermissivePolicy};
+
+use planets_service::event_bus::InMemoryEventBus;
 use planets_service::{configure_service, create_schema_with_context};
 
 mod common;
 
 #[actix_rt::test]
 async fn test_create_planet() {
-    env::set_var("DISABLE_AUTH", true.to_string());
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(PermissivePolicy),
+    );
 
     let service = test::init_service(
         App::new()
             .configure(configure_service)
-            .app_data(web::Data::new(create_schema_with_context(pool))),
+            .app_data(web::Data::new(schema)),
     )
     .await;
 
@@ -77,6 +86,246 @@ async fn test_create_planet() {
     common::check_planet(created_planet_json, 9, "Test planet", "ICE_GIANT", "10.7");
 }
 
+#[actix_rt::test]
+async fn test_update_planet() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(PermissivePolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let mutation = r#"
+        mutation($id: ID!, $name: String!, $meanRadius: BigDecimal!) {
+            updatePlanet(id: $id, planet: { name: $name, details: { meanRadius: $meanRadius } }) {
+                id
+                name
+                type
+                details {
+                    meanRadius
+                    mass
+                }
+            }
+        }
+        "#
+    .to_string();
+
+    let mut variables = Map::new();
+    variables.insert("id".to_string(), "4".into());
+    variables.insert("name".to_string(), "Red planet".into());
+    variables.insert("meanRadius".to_string(), "3390.0".into());
+
+    let request_body = GraphQLCustomRequest {
+        query: mutation,
+        variables,
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let response_data = response.data.expect("Response doesn't contain data");
+
+    let updated_planet_json = jsonpath::select(&response_data, "$.updatePlanet")
+        .expect("Can't get updated planet by JSON path")[0];
+
+    common::check_planet(
+        updated_planet_json,
+        4,
+        "Red planet",
+        "TERRESTRIAL_PLANET",
+        "3390.0",
+    );
+}
+
+#[actix_rt::test]
+async fn test_delete_planet() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(PermissivePolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let mutation = r#"
+        mutation {
+            deletePlanet(id: 8)
+        }
+        "#
+    .to_string();
+
+    let request_body = GraphQLCustomRequest {
+        query: mutation,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let response_data = response.data.expect("Response doesn't contain data");
+
+    let deleted_planet_id = jsonpath::select(&response_data, "$.deletePlanet")
+        .expect("Can't get deleted planet ID by JSON path")[0]
+        .as_str()
+        .expect("Can't get ID as str");
+    assert_eq!("8", deleted_planet_id);
+
+    let query = r#"
+        {
+            getPlanet(id: 8) {
+                id
+            }
+        }
+        "#
+    .to_string();
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let response_data = response.data.expect("Response doesn't contain data");
+
+    assert!(
+        jsonpath::select(&response_data, "$.getPlanet").expect("Can't get planet")[0].is_null()
+    );
+}
+
+#[actix_rt::test]
+async fn test_delete_planet_requires_permission() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let mutation = r#"
+        mutation {
+            deletePlanet(id: 8)
+        }
+        "#
+    .to_string();
+
+    let request_body = GraphQLCustomRequest {
+        query: mutation,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let errors = response.errors.expect("Response doesn't contain errors");
+    let error_code = jsonpath::select(&errors, "$[0].extensions.code")
+        .expect("Can't get error code by JSON path")[0]
+        .as_str()
+        .expect("Can't get error code as str");
+    assert_eq!("FORBIDDEN", error_code);
+}
+
+#[actix_rt::test]
+async fn test_create_planet_notifies_subscribers() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(PermissivePolicy),
+    );
+
+    let subscription = "
+        subscription {
+            latestPlanet {
+                name
+                type
+                details {
+                    meanRadius
+                }
+            }
+        }
+        ";
+
+    let mutation = r#"
+        mutation {
+            createPlanet(
+                planet: {
+                    name: "Test planet"
+                    type: DWARF_PLANET
+                    details: { meanRadius: "1188.3", mass: "1.303e+22" }
+                }
+            ) {
+                id
+            }
+        }
+        "#;
+
+    let mut stream = schema.execute_stream(subscription);
+
+    // the subscription is polled first, so it is established before the planet is created
+    let (subscription_response, mutation_response) =
+        futures::join!(stream.next(), schema.execute(mutation));
+
+    assert!(mutation_response.errors.is_empty());
+
+    let subscription_data = subscription_response
+        .expect("Subscription stream ended")
+        .data
+        .into_json()
+        .expect("Can't convert data to JSON");
+
+    let latest_planet_json = jsonpath::select(&subscription_data, "$.latestPlanet")
+        .expect("Can't get latest planet by JSON path")[0];
+    assert_eq!("Test planet", latest_planet_json["name"]);
+    assert_eq!("DWARF_PLANET", latest_planet_json["type"]);
+    assert_eq!("1188.3", latest_planet_json["details"]["meanRadius"]);
+}
+
 #[derive(Serialize)]
 struct GraphQLCustomRequest {
     query: String,
@@ -86,6 +335,5 @@ struct GraphQLCustomRequest {
 #[derive(Deserialize)]
 struct GraphQLCustomResponse {
     data: Option<serde_json::Value>,
-    #[allow(dead_code)]
     errors: Option<serde_json::Value>,
 }
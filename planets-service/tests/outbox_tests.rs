@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use testcontainers::clients::Cli;
+
+use planets_service::event_bus::{BoxError, EventBus, InMemoryEventBus, ReceivedMessage};
+use planets_service::outbox::{self, OutboxNotifier};
+use planets_service::persistence::model::NewOutboxMessageEntity;
+use planets_service::persistence::repository;
+
+mod common;
+
+/// Fails to publish the given number of messages, then delivers them in memory
+struct FlakyEventBus {
+    failures_left: AtomicUsize,
+    inner: InMemoryEventBus,
+}
+
+#[async_trait]
+impl EventBus for FlakyEventBus {
+    async fn publish(&self, key: &str, payload: &[u8]) -> Result<(), BoxError> {
+        let failed = self
+            .failures_left
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if failed {
+            return Err("Event bus is unavailable".into());
+        }
+        self.inner.publish(key, payload).await
+    }
+
+    fn subscribe(&self) -> Result<BoxStream<'static, ReceivedMessage>, BoxError> {
+        self.inner.subscribe()
+    }
+
+    async fn check_health(&self) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn test_failed_message_is_retried() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let event_bus = Arc::new(FlakyEventBus {
+        failures_left: AtomicUsize::new(1),
+        inner: InMemoryEventBus::default(),
+    });
+    let mut messages = event_bus.subscribe().expect("Can't subscribe");
+
+    let mut conn = pool.get().await.expect("Can't get DB connection");
+    let message = repository::create_outbox_message(
+        NewOutboxMessageEntity {
+            message_key: "1".to_string(),
+            payload: "payload".to_string(),
+            trace_context: None,
+        },
+        &mut conn,
+    )
+    .await
+    .expect("Can't create outbox message");
+
+    outbox::start_relay(
+        Arc::new(pool.clone()),
+        event_bus.clone(),
+        OutboxNotifier::default(),
+    );
+
+    // the first attempt fails, the next one is made after a second of backoff
+    let received = tokio::time::timeout(Duration::from_secs(10), messages.next())
+        .await
+        .expect("Message wasn't relayed")
+        .expect("Event bus is closed");
+    assert_eq!(b"payload".to_vec(), received.payload);
+
+    let mut delivered_message = None;
+    for _ in 0..10 {
+        let message = repository::get_outbox_message(message.id, &mut conn)
+            .await
+            .expect("Can't get outbox message");
+        if message.delivered_at.is_some() {
+            delivered_message = Some(message);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let delivered_message = delivered_message.expect("Message isn't marked as delivered");
+    assert_eq!(1, delivered_message.attempts);
+    assert_eq!(
+        Some("Event bus is unavailable"),
+        delivered_message.last_error.as_deref()
+    );
+}
+
+#[actix_rt::test]
+async fn test_messages_are_published_in_order_after_failure() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    // only the first message of the batch fails
+    let event_bus = Arc::new(FlakyEventBus {
+        failures_left: AtomicUsize::new(1),
+        inner: InMemoryEventBus::default(),
+    });
+    let mut messages = event_bus.subscribe().expect("Can't subscribe");
+
+    let mut conn = pool.get().await.expect("Can't get DB connection");
+    for payload in ["1", "2", "3"] {
+        repository::create_outbox_message(
+            NewOutboxMessageEntity {
+                message_key: "1".to_string(),
+                payload: payload.to_string(),
+                trace_context: None,
+            },
+            &mut conn,
+        )
+        .await
+        .expect("Can't create outbox message");
+    }
+
+    outbox::start_relay(
+        Arc::new(pool.clone()),
+        event_bus.clone(),
+        OutboxNotifier::default(),
+    );
+
+    // the rest of the batch waits for the failed message instead of its lease to expire
+    let mut received = Vec::new();
+    for _ in 0..3 {
+        let message = tokio::time::timeout(Duration::from_secs(10), messages.next())
+            .await
+            .expect("Message wasn't relayed")
+            .expect("Event bus is closed");
+        received.push(message.payload);
+    }
+    assert_eq!(vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()], received);
+}
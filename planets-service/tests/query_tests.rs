@@ -1,9 +1,15 @@
+use std::sync::Arc;
+
 use actix_web::{test, web, App};
 use jsonpath_lib as jsonpath;
 use serde::{Deserialize, Serialize};
-use serde_json::Map;
+use serde_json::{json, Map};
 use testcontainers::clients::Cli;
 
+use common_utils::permissions::EnforcingPolicy;
+use common_utils::persisted_queries::query_hash;
+
+use planets_service::event_bus::{EventBus, InMemoryEventBus};
 use planets_service::{configure_service, create_schema_with_context};
 
 mod common;
@@ -26,26 +32,36 @@ const PLANET_FRAGMENT: &str = "
 #[actix_rt::test]
 async fn test_get_planets() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
 
     let service = test::init_service(
         App::new()
             .configure(configure_service)
-            .app_data(web::Data::new(create_schema_with_context(pool))),
+            .app_data(web::Data::new(schema)),
     )
     .await;
 
     let query = "
         {
             getPlanets {
-                id
-                name
-                type
-                details {
-                    meanRadius
-                    mass
-                    ... on InhabitedPlanetDetails {
-                        population
+                edges {
+                    node {
+                        id
+                        name
+                        type
+                        details {
+                            meanRadius
+                            mass
+                            ... on InhabitedPlanetDetails {
+                                population
+                            }
+                        }
                     }
                 }
             }
@@ -66,7 +82,7 @@ async fn test_get_planets() {
     let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
 
     fn get_planet_as_json(all_planets: &serde_json::Value, index: i32) -> &serde_json::Value {
-        jsonpath::select(all_planets, &format!("$.getPlanets[{}]", index))
+        jsonpath::select(all_planets, &format!("$.getPlanets.edges[{}].node", index))
             .expect("Can't get planet by JSON path")[0]
     }
 
@@ -83,12 +99,18 @@ async fn test_get_planets() {
 #[actix_rt::test]
 async fn test_get_planet_by_id() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
 
     let service = test::init_service(
         App::new()
             .configure(configure_service)
-            .app_data(web::Data::new(create_schema_with_context(pool))),
+            .app_data(web::Data::new(schema)),
     )
     .await;
 
@@ -122,12 +144,18 @@ async fn test_get_planet_by_id() {
 #[actix_rt::test]
 async fn test_get_planet_by_id_with_variable() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
 
     let service = test::init_service(
         App::new()
             .configure(configure_service)
-            .app_data(web::Data::new(create_schema_with_context(pool))),
+            .app_data(web::Data::new(schema)),
     )
     .await;
 
@@ -158,6 +186,495 @@ async fn test_get_planet_by_id_with_variable() {
     common::check_planet(jupiter_json, 5, "Jupiter", "GAS_GIANT", "69911.0");
 }
 
+#[actix_rt::test]
+async fn test_get_planets_page() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let query = "
+        query testPlanetsPage($first: Int, $after: String) {
+            getPlanets(first: $first, after: $after) {
+                edges {
+                    node {
+                        ... planetFragment
+                    }
+                }
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+            }
+        }"
+    .to_string()
+        + PLANET_FRAGMENT;
+
+    let mut variables = Map::new();
+    variables.insert("first".to_string(), 3.into());
+
+    let request_body = GraphQLCustomRequest {
+        query: query.clone(),
+        variables,
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let first_page_edges = jsonpath::select(&response.data, "$.getPlanets.edges[*]")
+        .expect("Can't get edges by JSON path");
+    assert_eq!(3, first_page_edges.len());
+    assert!(
+        jsonpath::select(&response.data, "$.getPlanets.pageInfo.hasNextPage")
+            .expect("Can't get page info by JSON path")[0]
+            .as_bool()
+            .expect("Can't get hasNextPage as bool")
+    );
+    let end_cursor = jsonpath::select(&response.data, "$.getPlanets.pageInfo.endCursor")
+        .expect("Can't get page info by JSON path")[0]
+        .as_str()
+        .expect("Can't get endCursor as str")
+        .to_string();
+
+    let mut variables = Map::new();
+    variables.insert("first".to_string(), 3.into());
+    variables.insert("after".to_string(), end_cursor.into());
+
+    let request_body = GraphQLCustomRequest { query, variables };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let mars_json = jsonpath::select(&response.data, "$.getPlanets.edges[0].node")
+        .expect("Can't get planet by JSON path")[0];
+    common::check_planet(mars_json, 4, "Mars", "TERRESTRIAL_PLANET", "3389.5");
+}
+
+#[actix_rt::test]
+async fn test_get_planets_with_filter_and_order() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let query = "
+        query testFilteredPlanets($after: String) {
+            getPlanets(
+                filter: { types: [GAS_GIANT, ICE_GIANT], meanRadius: { min: \"25000\" } }
+                orderBy: { field: MASS, direction: DESC }
+                first: 2
+                after: $after
+            ) {
+                edges {
+                    node {
+                        ... planetFragment
+                    }
+                }
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+            }
+        }"
+    .to_string()
+        + PLANET_FRAGMENT;
+
+    let request_body = GraphQLCustomRequest {
+        query: query.clone(),
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let jupiter_json = jsonpath::select(&response.data, "$.getPlanets.edges[0].node")
+        .expect("Can't get planet by JSON path")[0];
+    common::check_planet(jupiter_json, 5, "Jupiter", "GAS_GIANT", "69911.0");
+    let saturn_json = jsonpath::select(&response.data, "$.getPlanets.edges[1].node")
+        .expect("Can't get planet by JSON path")[0];
+    common::check_planet(saturn_json, 6, "Saturn", "GAS_GIANT", "58232.0");
+
+    let end_cursor = jsonpath::select(&response.data, "$.getPlanets.pageInfo.endCursor")
+        .expect("Can't get page info by JSON path")[0]
+        .as_str()
+        .expect("Can't get endCursor as str")
+        .to_string();
+
+    let mut variables = Map::new();
+    variables.insert("after".to_string(), end_cursor.into());
+
+    let request_body = GraphQLCustomRequest { query, variables };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    // Neptune is excluded by the mean radius filter
+    let edges = jsonpath::select(&response.data, "$.getPlanets.edges[*].node")
+        .expect("Can't get planets by JSON path");
+    assert_eq!(1, edges.len());
+    common::check_planet(edges[0], 7, "Uranus", "ICE_GIANT", "25362.0");
+}
+
+#[actix_rt::test]
+async fn test_get_planet_with_invalid_id() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let query = r#"
+        {
+            getPlanet(id: "earth") {
+                id
+            }
+        }
+        "#
+    .to_string();
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert!(
+        jsonpath::select(&response.data, "$.getPlanet").expect("Can't get planet")[0].is_null()
+    );
+
+    let error_code = jsonpath::select(&response.errors, "$[0].extensions.code")
+        .expect("Can't get error code by JSON path")[0];
+    assert_eq!("BAD_USER_INPUT", error_code);
+}
+
+#[actix_rt::test]
+async fn test_too_complex_query_is_rejected() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let query = "
+        {
+            first: getPlanets(first: 100) {
+                edges {
+                    node {
+                        ...planetFragment
+                    }
+                }
+            }
+            second: getPlanets(first: 100) {
+                edges {
+                    node {
+                        ...planetFragment
+                    }
+                }
+            }
+        }
+        "
+    .to_string()
+        + PLANET_FRAGMENT;
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert!(response.data.is_null());
+
+    let error_code = jsonpath::select(&response.errors, "$[0].extensions.code")
+        .expect("Can't get error code by JSON path")[0];
+    assert_eq!("BAD_USER_INPUT", error_code);
+}
+
+#[actix_rt::test]
+async fn test_deep_introspection_query_is_allowed() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    // deeper than the default depth limit, like queries of GraphQL tools
+    let query = r#"
+        {
+            __schema {
+                types {
+                    fields {
+                        type {
+                            ofType {
+                                ofType {
+                                    ofType {
+                                        ofType {
+                                            ofType {
+                                                ofType {
+                                                    ofType {
+                                                        name
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        "#
+    .to_string();
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert!(response.errors.is_null());
+    assert!(!jsonpath::select(&response.data, "$.__schema.types")
+        .expect("Can't get types by JSON path")
+        .is_empty());
+}
+
+#[actix_rt::test]
+async fn test_persisted_query() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let query = "
+        {
+            getPlanet(id: 3) {
+                ...planetFragment
+            }
+        }
+        "
+    .to_string()
+        + PLANET_FRAGMENT;
+    let extensions = json!({
+        "persistedQuery": {
+            "version": 1,
+            "sha256Hash": query_hash(&query)
+        }
+    });
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(json!({ "extensions": extensions }))
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let error_code = jsonpath::select(&response.errors, "$[0].extensions.code")
+        .expect("Can't get error code by JSON path")[0];
+    assert_eq!("PERSISTED_QUERY_NOT_FOUND", error_code);
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(json!({ "query": query, "extensions": extensions }))
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert!(response.errors.is_null());
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(json!({ "extensions": extensions }))
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let earth_json =
+        jsonpath::select(&response.data, "$.getPlanet").expect("Can't get planet by JSON path")[0];
+    common::check_planet(earth_json, 3, "Earth", "TERRESTRIAL_PLANET", "6371.0");
+}
+
+#[actix_rt::test]
+async fn test_invalid_persisted_query_is_not_registered() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let schema = create_schema_with_context(
+        pool,
+        Arc::new(InMemoryEventBus::default()),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema)),
+    )
+    .await;
+
+    let query = "
+        {
+            getPlanet(id: 3) {
+                unknownField
+            }
+        }
+        "
+    .to_string();
+    let extensions = json!({
+        "persistedQuery": {
+            "version": 1,
+            "sha256Hash": query_hash(&query)
+        }
+    });
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(json!({ "query": query, "extensions": extensions }))
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    assert!(!response.errors.is_null());
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(json!({ "extensions": extensions }))
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let error_code = jsonpath::select(&response.errors, "$[0].extensions.code")
+        .expect("Can't get error code by JSON path")[0];
+    assert_eq!("PERSISTED_QUERY_NOT_FOUND", error_code);
+}
+
+#[actix_rt::test]
+async fn test_readiness() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let event_bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::default());
+    let schema = create_schema_with_context(
+        pool.clone(),
+        Arc::clone(&event_bus),
+        Arc::new(EnforcingPolicy),
+    );
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(schema))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::from(event_bus)),
+    )
+    .await;
+
+    let request = test::TestRequest::get().uri("/health/ready").to_request();
+
+    let response: serde_json::Value = test::call_and_read_body_json(&service, request).await;
+
+    assert_eq!("up", response["status"]);
+    assert_eq!("up", response["checks"]["database"]["status"]);
+    assert_eq!("up", response["checks"]["event_bus"]["status"]);
+}
+
 #[derive(Serialize)]
 struct GraphQLCustomRequest {
     query: String,
@@ -167,4 +684,6 @@ struct GraphQLCustomRequest {
 #[derive(Deserialize)]
 struct GraphQLCustomResponse {
     data: serde_json::Value,
+    #[serde(default)]
+    errors: serde_json::Value,
 }
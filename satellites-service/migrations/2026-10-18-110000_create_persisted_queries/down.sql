@@ -0,0 +1 @@
+drop table persisted_queries;
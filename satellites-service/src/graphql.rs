@@ -1,9 +1,14 @@
 use std::str::FromStr;
 
+use async_graphql::connection::{query, Connection};
 use async_graphql::*;
 use chrono::NaiveDate;
+use diesel::OptionalExtension;
 use strum_macros::EnumString;
 
+use common_utils::error::{self, ErrorCode, ServiceError, ServiceResult};
+use common_utils::pagination::{self, IdCursor, KeysetPage};
+
 use crate::get_conn_from_ctx;
 use crate::persistence::model::SatelliteEntity;
 use crate::persistence::repository;
@@ -14,22 +19,48 @@ pub struct Query;
 
 #[Object]
 impl Query {
-    async fn get_satellites(&self, ctx: &Context<'_>) -> Vec<Satellite> {
-        repository::get_all(&mut get_conn_from_ctx(ctx))
-            .expect("Can't get satellites")
-            .iter()
-            .map(Satellite::from)
-            .collect()
+    #[graphql(complexity = "pagination::page_complexity(first, last, child_complexity)")]
+    async fn get_satellites(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<IdCursor, Satellite>> {
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let page = KeysetPage::new(after, before, first, last)?;
+                let mut conn = get_conn_from_ctx(ctx).await?;
+                let satellite_entities = repository::get_page(&page, &mut conn).await?;
+                let satellites = satellite_entities
+                    .iter()
+                    .map(|s| Ok((s.id, Satellite::try_from(s)?)))
+                    .collect::<ServiceResult<Vec<_>>>()?;
+                Ok::<_, ServiceError>(page.into_connection(
+                    satellites,
+                    |(id, _)| *id,
+                    |(_, satellite)| satellite.clone(),
+                ))
+            },
+        )
+        .await
+        .map_err(|e| error::with_default_code(e, ErrorCode::BadUserInput))
     }
 
-    async fn get_satellite(&self, ctx: &Context<'_>, id: ID) -> Option<Satellite> {
-        let id = id
-            .to_string()
-            .parse::<i32>()
-            .expect("Can't get id from String");
-        repository::get(id, &mut get_conn_from_ctx(ctx))
-            .ok()
-            .map(|e| Satellite::from(&e))
+    async fn get_satellite(&self, ctx: &Context<'_>, id: ID) -> ServiceResult<Option<Satellite>> {
+        let id = error::parse_id(&id)?;
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        repository::get(id, &mut conn)
+            .await
+            .optional()?
+            .as_ref()
+            .map(Satellite::try_from)
+            .transpose()
     }
 
     #[graphql(entity)]
@@ -38,7 +69,7 @@ impl Query {
     }
 }
 
-#[derive(SimpleObject)]
+#[derive(SimpleObject, Clone)]
 struct Satellite {
     id: ID,
     name: String,
@@ -65,28 +96,31 @@ impl Planet {
         &self.id
     }
 
-    async fn satellites(&self, ctx: &Context<'_>) -> Vec<Satellite> {
-        let id = self
-            .id
-            .to_string()
-            .parse::<i32>()
-            .expect("Can't get id from String");
-        repository::get_by_planet_id(id, &mut get_conn_from_ctx(ctx))
-            .expect("Can't get satellites of planet")
+    #[graphql(complexity = "pagination::DEFAULT_PAGE_SIZE * child_complexity")]
+    async fn satellites(&self, ctx: &Context<'_>) -> ServiceResult<Vec<Satellite>> {
+        let id = error::parse_id(&self.id)?;
+        let mut conn = get_conn_from_ctx(ctx).await?;
+        repository::get_by_planet_id(id, &mut conn)
+            .await?
             .iter()
-            .map(Satellite::from)
+            .map(Satellite::try_from)
             .collect()
     }
 }
 
-impl From<&SatelliteEntity> for Satellite {
-    fn from(entity: &SatelliteEntity) -> Self {
-        Satellite {
+impl TryFrom<&SatelliteEntity> for Satellite {
+    type Error = ServiceError;
+
+    fn try_from(entity: &SatelliteEntity) -> Result<Self, Self::Error> {
+        let life_exists = LifeExists::from_str(entity.life_exists.as_str()).map_err(|_| {
+            ServiceError::internal(format!("Unknown life_exists value: {}", entity.life_exists))
+        })?;
+
+        Ok(Satellite {
             id: entity.id.into(),
             name: entity.name.clone(),
-            life_exists: LifeExists::from_str(entity.life_exists.as_str())
-                .expect("Can't convert &str to LifeExists"),
+            life_exists,
             first_spacecraft_landing_date: entity.first_spacecraft_landing_date,
-        }
+        })
     }
 }
@@ -1,10 +1,21 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql::{Context, EmptyMutation, EmptySubscription, Schema};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
-use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::PgConnection;
+use diesel_async::pooled_connection::deadpool::Object;
+use diesel_async::AsyncPgConnection;
 use diesel_migrations::MigrationHarness;
+use tracing::Instrument;
+
+use common_utils::error::ServiceResult;
+use common_utils::health;
+use common_utils::identity::Identity;
+use common_utils::metrics::{self, GraphQLMetrics};
+use common_utils::persisted_queries::{PersistedQueries, PersistedQueriesConfig};
+use common_utils::query_limits::QueryLimits;
+use common_utils::request_id::{RequestId, RequestIdInErrors};
+use common_utils::telemetry::{self, GraphQLTracing};
 
 use crate::graphql::{AppSchema, Query};
 use crate::persistence::connection::PgPool;
@@ -21,10 +32,30 @@ pub fn configure_service(cfg: &mut web::ServiceConfig) {
             .route(web::post().to(index))
             .route(web::get().to(index_playground)),
     );
+    cfg.route("/metrics", web::get().to(metrics::metrics));
+    cfg.route("/health/live", web::get().to(health::live));
+    cfg.route("/health/ready", web::get().to(health::ready));
 }
 
-async fn index(schema: web::Data<AppSchema>, req: GraphQLRequest) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
+async fn index(
+    schema: web::Data<AppSchema>,
+    http_req: HttpRequest,
+    request_id: Option<web::ReqData<RequestId>>,
+    identity: Option<web::ReqData<Identity>>,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    let mut query = req.into_inner();
+    if let Some(request_id) = request_id {
+        query = query.data(request_id.into_inner());
+    }
+    if let Some(identity) = identity {
+        query = query.data(identity.into_inner().permissions);
+    }
+    schema
+        .execute(query)
+        .instrument(telemetry::request_span(&http_req))
+        .await
+        .into()
 }
 
 async fn index_playground() -> HttpResponse {
@@ -35,18 +66,23 @@ async fn index_playground() -> HttpResponse {
 
 pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, EmptyMutation, EmptySubscription> {
     Schema::build(Query, EmptyMutation, EmptySubscription)
+        .extension(GraphQLMetrics)
+        .extension(GraphQLTracing)
+        .extension(RequestIdInErrors)
+        .extension(PersistedQueries::new(
+            &PersistedQueriesConfig::from_env().expect("Can't configure persisted queries"),
+            pool.clone(),
+        ))
+        .extension(QueryLimits::from_env().expect("Can't configure query limits"))
         .data(pool)
         .finish()
 }
 
-pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
+pub fn run_migrations(conn: &mut PgConnection) {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("Failed to run database migrations");
 }
 
-pub fn get_conn_from_ctx(ctx: &Context<'_>) -> PooledConnection<ConnectionManager<PgConnection>> {
-    ctx.data::<PgPool>()
-        .expect("Can't get pool")
-        .get()
-        .expect("Can't get DB connection")
+pub async fn get_conn_from_ctx(ctx: &Context<'_>) -> ServiceResult<Object<AsyncPgConnection>> {
+    Ok(ctx.data::<PgPool>()?.get().await?)
 }
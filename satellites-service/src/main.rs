@@ -2,28 +2,51 @@ extern crate satellites_service;
 
 use std::env;
 
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
 
-use satellites_service::persistence::connection::create_connection_pool;
+use common_utils::identity::{verify_identity, IdentityKey};
+use common_utils::metrics::track_http_requests;
+use common_utils::request_id::assign_request_id;
+use common_utils::telemetry::{self, TraceExporter};
+
+use satellites_service::persistence::connection::{
+    create_connection_pool, establish_migration_connection,
+};
 use satellites_service::{configure_service, create_schema_with_context, run_migrations};
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let pool = create_connection_pool();
-    run_migrations(&mut pool.get().expect("Can't get DB connection"));
+    let trace_exporter = TraceExporter::from_env().expect("Can't configure tracing");
+    telemetry::init_tracing("satellites-service", trace_exporter)
+        .expect("Can't initialize tracing");
+    let pool = create_connection_pool().await;
+    run_migrations(&mut establish_migration_connection());
+
+    let identity_key =
+        web::Data::new(IdentityKey::from_env().expect("Can't configure internal identity key"));
+    let schema = web::Data::new(create_schema_with_context(pool.clone()));
 
-    let schema = web::Data::new(create_schema_with_context(pool));
+    let pool = web::Data::new(pool);
 
     let server_port = env::var("SERVER_PORT").expect("Can't get server port");
 
     HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(verify_identity))
+            .wrap(from_fn(track_http_requests))
+            .wrap(from_fn(assign_request_id))
             .configure(configure_service)
             .app_data(schema.clone())
+            .app_data(identity_key.clone())
+            .app_data(pool.clone())
     })
     .bind(format!("0.0.0.0:{}", server_port))?
     .run()
-    .await
+    .await?;
+
+    telemetry::shutdown_tracing();
+    Ok(())
 }
@@ -1,23 +1,47 @@
 use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use tracing::instrument;
+
+use common_utils::pagination::KeysetPage;
 
 use crate::persistence::model::SatelliteEntity;
 use crate::persistence::schema::satellites;
 
-pub fn get_all(conn: &mut PgConnection) -> QueryResult<Vec<SatelliteEntity>> {
-    use crate::persistence::schema::satellites::dsl::*;
+#[instrument(skip_all)]
+pub async fn get_page(
+    page: &KeysetPage,
+    conn: &mut AsyncPgConnection,
+) -> QueryResult<Vec<SatelliteEntity>> {
+    let mut query = satellites::table.into_boxed();
+
+    if let Some(after) = page.after {
+        query = query.filter(satellites::id.gt(after));
+    }
+    if let Some(before) = page.before {
+        query = query.filter(satellites::id.lt(before));
+    }
+
+    query = if page.backward {
+        query.order(satellites::id.desc())
+    } else {
+        query.order(satellites::id.asc())
+    };
 
-    satellites.load(conn)
+    query.limit(page.fetch_limit()).load(conn).await
 }
 
-pub fn get(id: i32, conn: &mut PgConnection) -> QueryResult<SatelliteEntity> {
-    satellites::table.find(id).get_result(conn)
+#[instrument(skip_all)]
+pub async fn get(id: i32, conn: &mut AsyncPgConnection) -> QueryResult<SatelliteEntity> {
+    satellites::table.find(id).get_result(conn).await
 }
 
-pub fn get_by_planet_id(
+#[instrument(skip_all)]
+pub async fn get_by_planet_id(
     planet_id: i32,
-    conn: &mut PgConnection,
+    conn: &mut AsyncPgConnection,
 ) -> QueryResult<Vec<SatelliteEntity>> {
     satellites::table
         .filter(satellites::planet_id.eq(planet_id))
         .load(conn)
+        .await
 }
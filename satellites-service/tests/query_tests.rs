@@ -1,5 +1,6 @@
 use std::str::FromStr;
 
+use actix_web::middleware::from_fn;
 use actix_web::{test, web, App};
 use chrono::NaiveDate;
 use jsonpath_lib as jsonpath;
@@ -7,6 +8,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::Map;
 use testcontainers::clients::Cli;
 
+use common_utils::request_id::assign_request_id;
+
 use satellites_service::graphql::LifeExists::{self, NoData, OpenQuestion};
 use satellites_service::{configure_service, create_schema_with_context};
 
@@ -24,7 +27,7 @@ const TEST_FIELDS_FRAGMENT: &str = "
 #[actix_rt::test]
 async fn test_get_satellites() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
 
     let service = test::init_service(
         App::new()
@@ -36,7 +39,11 @@ async fn test_get_satellites() {
     let query = "
         {
             getSatellites {
-                ... testFields
+                edges {
+                    node {
+                        ... testFields
+                    }
+                }
             }
         }
         "
@@ -58,8 +65,11 @@ async fn test_get_satellites() {
     let response_data = response.data.expect("Response doesn't contain data");
 
     fn get_satellite_as_json(all_satellites: &serde_json::Value, index: i32) -> &serde_json::Value {
-        jsonpath::select(all_satellites, &format!("$.getSatellites[{}]", index))
-            .expect("Can't get satellite by JSON path")[0]
+        jsonpath::select(
+            all_satellites,
+            &format!("$.getSatellites.edges[{}].node", index),
+        )
+        .expect("Can't get satellite by JSON path")[0]
     }
 
     let moon_json = get_satellite_as_json(&response_data, 0);
@@ -79,7 +89,7 @@ async fn test_get_satellites() {
 #[actix_rt::test]
 async fn test_get_satellite() {
     let docker = Cli::default();
-    let (_pg_container, pool) = common::setup(&docker);
+    let (_pg_container, pool) = common::setup(&docker).await;
 
     let service = test::init_service(
         App::new()
@@ -124,6 +134,113 @@ async fn test_get_satellite() {
     );
 }
 
+#[actix_rt::test]
+async fn test_get_last_satellites() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = "
+        {
+            getSatellites(last: 2) {
+                edges {
+                    node {
+                        ... testFields
+                    }
+                }
+                pageInfo {
+                    hasPreviousPage
+                    hasNextPage
+                }
+            }
+        }
+        "
+    .to_string()
+        + TEST_FIELDS_FRAGMENT;
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .set_json(&request_body)
+        .to_request();
+
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let response_data = response.data.expect("Response doesn't contain data");
+
+    let edges = jsonpath::select(&response_data, "$.getSatellites.edges[*].node")
+        .expect("Can't get satellites by JSON path");
+    assert_eq!(2, edges.len());
+    check_satellite(edges[0], "Miranda", None, NoData);
+    check_satellite(edges[1], "Triton", None, NoData);
+
+    let has_previous_page =
+        jsonpath::select(&response_data, "$.getSatellites.pageInfo.hasPreviousPage")
+            .expect("Can't get page info by JSON path")[0]
+            .as_bool()
+            .expect("Can't get hasPreviousPage as bool");
+    assert!(has_previous_page);
+}
+
+#[actix_rt::test]
+async fn test_error_contains_request_id() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker).await;
+
+    let service = test::init_service(
+        App::new()
+            .wrap(from_fn(assign_request_id))
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let query = r#"
+        {
+            getSatellite(id: "moon") {
+                id
+            }
+        }
+        "#
+    .to_string();
+
+    let request_body = GraphQLCustomRequest {
+        query,
+        variables: Map::new(),
+    };
+
+    let request = test::TestRequest::post()
+        .uri("/")
+        .insert_header(("x-request-id", "test-request"))
+        .set_json(&request_body)
+        .to_request();
+
+    let response = test::call_service(&service, request).await;
+    assert_eq!(
+        "test-request",
+        response
+            .headers()
+            .get("x-request-id")
+            .expect("Response doesn't contain request ID")
+    );
+
+    let response: GraphQLCustomResponse = test::read_body_json(response).await;
+    let errors = response.errors.expect("Response doesn't contain errors");
+    let request_id = jsonpath::select(&errors, "$[0].extensions.requestId")
+        .expect("Can't get request ID by JSON path")[0];
+    assert_eq!("test-request", request_id);
+}
+
 fn check_satellite(
     satellite_json: &serde_json::Value,
     name: &str,
@@ -173,6 +290,5 @@ struct GraphQLCustomRequest {
 #[derive(Deserialize)]
 struct GraphQLCustomResponse {
     data: Option<serde_json::Value>,
-    #[allow(dead_code)]
     errors: Option<serde_json::Value>,
 }